@@ -1,11 +1,13 @@
-use sp_core::{Pair, Public, sr25519};
+use sp_core::{Pair, Public, sr25519, OpaquePeerId};
 use node_template_runtime::{
-	AccountId, AuraConfig, BalancesConfig, GenesisConfig, GrandpaConfig,
-	SudoConfig, SystemConfig, WASM_BINARY, Signature
+	AccountId, AuraConfig, Balance, BalancesConfig, CouncilConfig, ExchangeConfig, GenesisConfig,
+	GrandpaConfig, ImOnlineConfig, NodeAuthorizationConfig, SessionConfig, StakingConfig, SudoConfig, SystemConfig,
+	StakerStatus, TokenConfig, WASM_BINARY, DOLLARS, Signature, opaque::SessionKeys, exchange_accounts,
 };
+use pallet_im_online::sr25519::AuthorityId as ImOnlineId;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use sp_finality_grandpa::AuthorityId as GrandpaId;
-use sp_runtime::traits::{Verify, IdentifyAccount};
+use sp_runtime::{Perbill, traits::{Verify, IdentifyAccount}};
 use sc_service::ChainType;
 
 use node_template_runtime::ContractsConfig;
@@ -33,14 +35,32 @@ pub fn get_account_id_from_seed<TPublic: Public>(seed: &str) -> AccountId where
 	AccountPublic::from(get_from_seed::<TPublic>(seed)).into_account()
 }
 
-/// Generate an Aura authority key.
-pub fn authority_keys_from_seed(s: &str) -> (AuraId, GrandpaId) {
+/// Balance each genesis validator's stash bonds.
+const STASH: Balance = 1 << 50;
+
+/// Generate a validator's stash and controller accounts and its session keys.
+pub fn authority_keys_from_seed(s: &str) -> (AccountId, AccountId, AuraId, GrandpaId, ImOnlineId) {
 	(
+		get_account_id_from_seed::<sr25519::Public>(&format!("{}//stash", s)),
+		get_account_id_from_seed::<sr25519::Public>(s),
 		get_from_seed::<AuraId>(s),
 		get_from_seed::<GrandpaId>(s),
+		get_from_seed::<ImOnlineId>(s),
+	)
+}
+
+/// A well-known node from its base58 `peer_id` and the seed of its `owner`.
+fn well_known_node(peer_id: &str, owner: &str) -> (OpaquePeerId, AccountId) {
+	(
+		OpaquePeerId(bs58::decode(peer_id).into_vec().expect("static peer ids are valid; qed")),
+		get_account_id_from_seed::<sr25519::Public>(owner),
 	)
 }
 
+fn session_keys(aura: AuraId, grandpa: GrandpaId, im_online: ImOnlineId) -> SessionKeys {
+	SessionKeys { aura, grandpa, im_online }
+}
+
 pub fn development_config() -> Result<ChainSpec, String> {
 	let wasm_binary = WASM_BINARY.ok_or("Development wasm binary not available".to_string())?;
 
@@ -52,7 +72,7 @@ pub fn development_config() -> Result<ChainSpec, String> {
 		ChainType::Development,
 		move || testnet_genesis(
 			wasm_binary,
-			// Initial PoA authorities
+			// Initial validators
 			vec![
 				authority_keys_from_seed("Alice"),
 			],
@@ -91,7 +111,7 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 		ChainType::Local,
 		move || testnet_genesis(
 			wasm_binary,
-			// Initial PoA authorities
+			// Initial validators
 			vec![
 				authority_keys_from_seed("Alice"),
 				authority_keys_from_seed("Bob"),
@@ -131,11 +151,13 @@ pub fn local_testnet_config() -> Result<ChainSpec, String> {
 /// Configure initial storage state for FRAME modules.
 fn testnet_genesis(
 	wasm_binary: &[u8],
-	initial_authorities: Vec<(AuraId, GrandpaId)>,
+	initial_authorities: Vec<(AccountId, AccountId, AuraId, GrandpaId, ImOnlineId)>,
 	root_key: AccountId,
 	endowed_accounts: Vec<AccountId>,
 	enable_println: bool,
 ) -> GenesisConfig {
+	let (exchange_operation, exchange_vault) = exchange_accounts();
+
 	GenesisConfig {
 		frame_system: Some(SystemConfig {
 			// Add Wasm runtime to storage.
@@ -143,19 +165,65 @@ fn testnet_genesis(
 			changes_trie_config: Default::default(),
 		}),
 		pallet_balances: Some(BalancesConfig {
-			// Configure endowed accounts with initial balance of 1 << 60.
-			balances: endowed_accounts.iter().cloned().map(|k|(k, 1 << 60)).collect(),
+			// Configure endowed accounts with initial balance of 1 << 60. The exchange
+			// accounts get enough to stay above the existential deposit.
+			balances: endowed_accounts.iter().cloned().map(|k|(k, 1 << 60))
+				.chain(vec![
+					(exchange_operation.clone(), DOLLARS),
+					(exchange_vault.clone(), DOLLARS),
+				])
+				.collect(),
 		}),
+		// Aura and GRANDPA authorities come from the session keys below.
 		pallet_aura: Some(AuraConfig {
-			authorities: initial_authorities.iter().map(|x| (x.0.clone())).collect(),
+			authorities: vec![],
 		}),
 		pallet_grandpa: Some(GrandpaConfig {
-			authorities: initial_authorities.iter().map(|x| (x.1.clone(), 1)).collect(),
+			authorities: vec![],
+		}),
+		pallet_staking: Some(StakingConfig {
+			validator_count: initial_authorities.len() as u32 * 2,
+			minimum_validator_count: initial_authorities.len() as u32,
+			stakers: initial_authorities.iter()
+				.map(|x| (x.0.clone(), x.1.clone(), STASH, StakerStatus::Validator))
+				.collect(),
+			invulnerables: initial_authorities.iter().map(|x| x.0.clone()).collect(),
+			slash_reward_fraction: Perbill::from_percent(10),
+			..Default::default()
+		}),
+		pallet_session: Some(SessionConfig {
+			keys: initial_authorities.iter()
+				.map(|x| (x.0.clone(), x.0.clone(), session_keys(x.2.clone(), x.3.clone(), x.4.clone())))
+				.collect(),
+		}),
+		pallet_im_online: Some(ImOnlineConfig {
+			keys: vec![],
 		}),
 		pallet_sudo: Some(SudoConfig {
 			// Assign network admin rights.
 			key: root_key,
 		}),
+		pallet_exchange: Some(ExchangeConfig {
+			account_operation: exchange_operation,
+			account_vault: exchange_vault,
+			default_pair_limits: Default::default(),
+			// No tokens exist at genesis, so there is nothing to pair yet.
+			pairs: vec![],
+			native_pairs: vec![],
+		}),
+		pallet_node_authorization: Some(NodeAuthorizationConfig {
+			// Nodes started with `--node-key` 0x00..01 and 0x00..02, owned by Alice and Bob.
+			nodes: vec![
+				well_known_node("12D3KooWBmAwcd4PJNJvfV89HwE48nwkRmAgo8Vy3uQEyNNHBox2", "Alice"),
+				well_known_node("12D3KooWQYV9dGMFoRzNStwpXztXaBUjtPqi6aU76ZgUriHhKust", "Bob"),
+			],
+		}),
+		pallet_token: Some(TokenConfig {}),
+		pallet_collective_Instance1: Some(CouncilConfig {
+			// Start with the endowed accounts on the council.
+			members: endowed_accounts.clone(),
+			phantom: Default::default(),
+		}),
 
         pallet_contracts: Some(ContractsConfig {
             current_schedule: pallet_contracts::Schedule {
@@ -15,6 +15,7 @@ use sp_transaction_pool::TransactionPool;
 
 use node_template_runtime::{opaque::Block, AccountId, Balance, Index, BlockNumber};
 use pallet_contracts_rpc::{Contracts, ContractsApi};
+use pallet_exchange_rpc::{Exchange, ExchangeApi};
 
 
 /// Full client dependencies.
@@ -37,6 +38,7 @@ pub fn create_full<C, P>(
 	C::Api: substrate_frame_rpc_system::AccountNonceApi<Block, AccountId, Index>,
 	C::Api: pallet_contracts_rpc::ContractsRuntimeApi<Block, AccountId, Balance, BlockNumber>,
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
+	C::Api: pallet_exchange_rpc::ExchangeRuntimeApi<Block, Balance, BlockNumber>,
 	C::Api: BlockBuilder<Block>,
 	P: TransactionPool + 'static,
 {
@@ -66,6 +68,10 @@ pub fn create_full<C, P>(
 	io.extend_with(
         ContractsApi::to_delegate(Contracts::new(client.clone()))
 	);
+
+	io.extend_with(
+		ExchangeApi::to_delegate(Exchange::new(client.clone()))
+	);
 	
 	io
 }
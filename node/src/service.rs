@@ -81,6 +81,14 @@ pub fn new_partial(config: &Configuration) -> Result<sc_service::PartialComponen
 
 /// Builds a new service for a full client.
 pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
+	// `pallet_node_authorization` filters peers from the offchain worker, so a full node
+	// without it would connect to any peer.
+	if !config.offchain_worker.enabled {
+		return Err(ServiceError::Other(
+			"node authorization needs the offchain worker: run with `--offchain-worker always`".into()
+		));
+	}
+
 	let sc_service::PartialComponents {
 		client, backend, mut task_manager, import_queue, keystore, select_chain, transaction_pool,
 		inherent_data_providers,
@@ -103,11 +111,9 @@ pub fn new_full(config: Configuration) -> Result<TaskManager, ServiceError> {
 			finality_proof_provider: Some(finality_proof_provider.clone()),
 		})?;
 
-	if config.offchain_worker.enabled {
-		sc_service::build_offchain_workers(
-			&config, backend.clone(), task_manager.spawn_handle(), client.clone(), network.clone(),
-		);
-	}
+	sc_service::build_offchain_workers(
+		&config, backend.clone(), task_manager.spawn_handle(), client.clone(), network.clone(),
+	);
 
 	let role = config.role.clone();
 	let force_authoring = config.force_authoring;
@@ -0,0 +1,24 @@
+//! Runtime API definition for the exchange pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use parity_scale_codec::Codec;
+use sp_std::prelude::*;
+
+pub use pallet_exchange::{Candle, Market};
+
+sp_api::decl_runtime_apis! {
+	pub trait ExchangeApi<Balance, BlockNumber> where
+		Balance: Codec,
+		BlockNumber: Codec,
+	{
+		/// Ratio of the most recent trade on `market`.
+		fn last_price(market: Market) -> Option<Balance>;
+		/// Target volume traded on `market` over the last day.
+		fn volume_24h(market: Market) -> Balance;
+		/// Candles of `market` overlapping blocks `from..=to`, oldest first.
+		fn candles(market: Market, from: BlockNumber, to: BlockNumber) -> Vec<Candle<Balance, BlockNumber>>;
+		/// Time-weighted average ratio of `market` over at least the last `window` blocks.
+		fn twap(market: Market, window: BlockNumber) -> Option<Balance>;
+	}
+}
@@ -0,0 +1,100 @@
+//! RPC interface for the exchange pallet's market data.
+
+use std::sync::Arc;
+
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use parity_scale_codec::Codec;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
+
+pub use pallet_exchange_rpc_runtime_api::{Candle, ExchangeApi as ExchangeRuntimeApi, Market};
+
+#[rpc]
+pub trait ExchangeApi<BlockHash, Balance, BlockNumber> {
+	/// Ratio of the most recent trade on `market`.
+	#[rpc(name = "exchange_lastPrice")]
+	fn last_price(&self, market: Market, at: Option<BlockHash>) -> Result<Option<Balance>>;
+
+	/// Target volume traded on `market` over the last day.
+	#[rpc(name = "exchange_volume24h")]
+	fn volume_24h(&self, market: Market, at: Option<BlockHash>) -> Result<Balance>;
+
+	/// Candles of `market` overlapping blocks `from..=to`, oldest first.
+	#[rpc(name = "exchange_candles")]
+	fn candles(
+		&self,
+		market: Market,
+		from: BlockNumber,
+		to: BlockNumber,
+		at: Option<BlockHash>
+	) -> Result<Vec<Candle<Balance, BlockNumber>>>;
+
+	/// Time-weighted average ratio of `market` over at least the last `window` blocks.
+	#[rpc(name = "exchange_twap")]
+	fn twap(&self, market: Market, window: BlockNumber, at: Option<BlockHash>) -> Result<Option<Balance>>;
+}
+
+/// Exchange RPC methods backed by the runtime API.
+pub struct Exchange<C, B> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<B>,
+}
+
+impl<C, B> Exchange<C, B> {
+	pub fn new(client: Arc<C>) -> Self {
+		Self { client, _marker: Default::default() }
+	}
+}
+
+/// Error code for a failed runtime call.
+const RUNTIME_ERROR: i64 = 1;
+
+fn runtime_error(e: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: "Unable to query exchange data.".into(),
+		data: Some(format!("{:?}", e).into()),
+	}
+}
+
+impl<C, Block, Balance, BlockNumber> ExchangeApi<<Block as BlockT>::Hash, Balance, BlockNumber>
+	for Exchange<C, Block>
+where
+	Block: BlockT,
+	C: Send + Sync + 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+	C::Api: ExchangeRuntimeApi<Block, Balance, BlockNumber>,
+	Balance: Codec,
+	BlockNumber: Codec,
+{
+	fn last_price(&self, market: Market, at: Option<<Block as BlockT>::Hash>) -> Result<Option<Balance>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.last_price(&at, market).map_err(runtime_error)
+	}
+
+	fn volume_24h(&self, market: Market, at: Option<<Block as BlockT>::Hash>) -> Result<Balance> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.volume_24h(&at, market).map_err(runtime_error)
+	}
+
+	fn candles(
+		&self,
+		market: Market,
+		from: BlockNumber,
+		to: BlockNumber,
+		at: Option<<Block as BlockT>::Hash>
+	) -> Result<Vec<Candle<Balance, BlockNumber>>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.candles(&at, market, from, to).map_err(runtime_error)
+	}
+
+	fn twap(&self, market: Market, window: BlockNumber, at: Option<<Block as BlockT>::Hash>) -> Result<Option<Balance>> {
+		let api = self.client.runtime_api();
+		let at = BlockId::hash(at.unwrap_or_else(|| self.client.info().best_hash));
+		api.twap(&at, market, window).map_err(runtime_error)
+	}
+}
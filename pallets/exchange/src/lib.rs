@@ -6,34 +6,91 @@ use frame_support::{
 	decl_module, 
 	decl_storage, 
 	ensure, 
-	//dispatch::DispatchResult,
+	transactional,
+	dispatch::DispatchResult,
+	storage::IterableStorageDoubleMap,
+	weights::Weight,
 	traits::{
 		Currency, 
-		//Get,
+		EnsureOrigin,
+		Get,
 		ReservableCurrency, 
-		ExistenceRequirement::AllowDeath
+		BalanceStatus,
+		ExistenceRequirement::{AllowDeath, KeepAlive},
 	},
 };
 use frame_system::{
 	self as system, 
 	ensure_signed,
-	ensure_root
 };
 use parity_scale_codec::{
 	Decode, 
 	Encode
 };
+use sp_runtime::{
+	PerThing,
+	Permill,
+	RuntimeDebug,
+	helpers_128bit::multiply_by_rational,
+	traits::{
+		CheckedAdd,
+		CheckedSub,
+		One,
+		SaturatedConversion,
+		Saturating,
+		Zero
+	},
+};
 use sp_std::prelude::*;
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+
+use pallet_token::{self as Token, KycProvider};
 
-use pallet_token as Token;
 
+pub mod migrations;
+
+#[cfg(test)]
+mod mock;
 
 #[cfg(test)]
 mod tests;
 
 pub trait Trait: system::Trait + pallet_token::Trait   {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
-	type Currency: ReservableCurrency<Self::AccountId>;
+	/// Currency the order deposits are reserved in.
+	type Currency: ReservableCurrency<Self::AccountId, Balance = BalanceOf<Self>>;
+	/// Maximum number of resting orders one account may hold across all pairs.
+	type MaxOpenOrdersPerUser: Get<u32>;
+	/// Maximum number of resting orders on each side of a single pair.
+	type MaxOrdersPerSide: Get<u32>;
+	/// Maximum number of trigger orders waiting on a single market.
+	type MaxTriggersPerMarket: Get<u32>;
+	/// Matching steps `on_finalize` may take per block: visiting a market, trying one
+	/// pair of crossing orders, checking a market's triggers or activating one of them.
+	/// Work left over carries on next block.
+	type MaxMatchingSteps: Get<u32>;
+	/// Deposit reserved for every resting order, released when it is filled or cancelled.
+	type OrderDeposit: Get<BalanceOf<Self>>;
+	/// Origin allowed to create and edit pairs.
+	type ListingOrigin: EnsureOrigin<Self::Origin>;
+	/// Origin allowed to change the trade fee and the default pair limits with `set_params`.
+	type FeeOrigin: EnsureOrigin<Self::Origin>;
+	/// Origin allowed to set the operation and vault accounts.
+	type AdminOrigin: EnsureOrigin<Self::Origin>;
+	/// Bond reserved from an account proposing a pair, slashed if the listing is rejected.
+	type ListingBond: Get<BalanceOf<Self>>;
+	/// Blocks `ListingOrigin` has to reject a proposed pair before it activates.
+	type ListingChallengePeriod: Get<Self::BlockNumber>;
+	/// Number of blocks covered by one candle.
+	type CandlePeriod: Get<Self::BlockNumber>;
+	/// Number of blocks `volume_24h` sums over, usually one day.
+	type VolumeWindow: Get<Self::BlockNumber>;
+	/// Minimum number of blocks between two price observations kept for the oracle.
+	type OracleGranularity: Get<Self::BlockNumber>;
+	/// Number of price observations kept per market. Together with `OracleGranularity`
+	/// this bounds the longest window `Oracle::twap` can answer.
+	type OracleObservations: Get<u32>;
 	// type AccountOperation: Get<Self::AccountId>;
 	// type AccountVault: Get<Self::AccountId>;
 
@@ -49,8 +106,20 @@ pub type PairNativeIndex = u128;
 pub type TradeNativeIndex = u128;
 pub type BuyOrderNativeIndex = u128;
 pub type SellOrderNativeIndex = u128;
+pub type TriggerOrderIndex = u128;
+pub type PairProposalIndex = u128;
 pub type TokenIndex = u32;
 
+/// Fixed-point scale of every order and trade `ratio`.
+///
+/// Each pair is quoted in its `base` token: `ratio` is the amount of `base` paid for
+/// one unit of `target`, multiplied by `PRICE_SCALE`. Buy orders escrow and carry
+/// their remaining `volume` in `base`, sell orders escrow and carry theirs in `target`.
+/// A fill at `ratio` moves `target * ratio / PRICE_SCALE` of `base` from the buyer to
+/// the seller and `target` from the seller to the buyer, rounding in favour of the
+/// escrow so that neither side is ever paid more than was locked for it.
+pub const PRICE_SCALE: u128 = 1_000_000_000_000;
+
 type AccountIdOf<T> = <T as system::Trait>::AccountId;
 type BalanceOf<T> = <<T as pallet_token::Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
 
@@ -62,6 +131,11 @@ type BuyOrderInfoOf<T> = BuyOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system
 type BuyOrderNativeInfoOf<T> = BuyOrderNativeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 type SellOrderInfoOf<T> = SellOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 type SellOrderNativeInfoOf<T> = SellOrderNativeInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type PriceObservationOf<T> = PriceObservation<<T as system::Trait>::BlockNumber>;
+type PairLimitsOf<T> = PairLimits<BalanceOf<T>>;
+type CandleOf<T> = Candle<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type PairProposalInfoOf<T> = PairProposalInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type TriggerOrderInfoOf<T> = TriggerOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 
 
 #[derive(Encode, Decode, Default, PartialEq, Eq)]
@@ -92,6 +166,7 @@ pub struct BuyOrderInfo<AccountId, Balance, BlockNumber> {
 	buyer: AccountId,
 	volume: Balance,
 	ratio: Balance,
+	deposit: Balance,
 	created: BlockNumber
 }
 
@@ -103,6 +178,7 @@ pub struct BuyOrderNativeInfo<AccountId, Balance, BlockNumber> {
 	buyer: AccountId,
 	volume: Balance,
 	ratio: Balance,
+	deposit: Balance,
 	created: BlockNumber
 }
 
@@ -114,6 +190,7 @@ pub struct SellOrderInfo<AccountId, Balance, BlockNumber> {
 	seller: AccountId,
 	volume: Balance,
 	ratio: Balance,
+	deposit: Balance,
 	created: BlockNumber
 }
 
@@ -125,6 +202,7 @@ pub struct SellOrderNativeInfo<AccountId, Balance, BlockNumber> {
 	seller: AccountId,
 	volume: Balance,
 	ratio: Balance,
+	deposit: Balance,
 	created: BlockNumber
 }
 
@@ -150,15 +228,171 @@ pub struct TradeNativeInfo<AccountId, Balance,  BlockNumber> {
 	ratio: Balance,
 	created: BlockNumber
 }
+/// Storage layouts of this pallet, oldest first. See `migrations`.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+	/// Orders without deposits, `Vec` order books, global minimums.
+	V0,
+	/// Order deposits, indexed order books, per-pair limits.
+	V1,
+	/// Trigger orders counted per market.
+	V2,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V0
+	}
+}
+
+/// Accounts the exchange is operated through.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum ExchangeAccount {
+	/// Holds the escrow of every open order.
+	Operation,
+	/// Receives trading fees and slashed bonds.
+	Vault,
+}
+
+/// Order size and price bounds of one market. Zero disables a bound.
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct PairLimits<Balance> {
+	/// Smallest order in the target token. Sell orders left with less are closed.
+	pub min_amount: Balance,
+	/// Smallest order value in the base token. Buy orders left with less are closed.
+	pub min_notional: Balance,
+	/// Lowest ratio an order may be placed at.
+	pub min_price: Balance,
+	/// Highest ratio an order may be placed at.
+	pub max_price: Balance,
+}
+
+/// A configurable exchange parameter and its new value.
+#[derive(Encode, Decode, Clone, PartialEq, Eq, RuntimeDebug)]
+pub enum ExchangeParam<Balance> {
+	/// Limits given to newly listed pairs.
+	DefaultPairLimits(PairLimits<Balance>),
+	/// Share of each leg of a trade, and of a cancelled order's remainder, sent to the vault.
+	TradeFee(Permill),
+}
+
+/// A pair awaiting its challenge period. `base` is `None` for a native pair.
+#[derive(Encode, Decode, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct PairProposalInfo<AccountId, Balance, BlockNumber> {
+	proposer: AccountId,
+	base: Option<u32>,
+	target: u32,
+	bond: Balance,
+	activates: BlockNumber,
+}
+
+/// A book that orders can be placed on.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub enum Market {
+	/// Token pair, see `Pair`.
+	Pair(PairIndex),
+	/// Native currency pair, see `PairNative`.
+	Native(PairNativeIndex),
+}
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum OrderSide {
+	Buy,
+	Sell,
+}
+
+/// How a trigger order behaves once the last price of its market crosses `trigger`.
+///
+/// Stop orders trigger when the price moves against the position: a buy stop at or
+/// above `trigger`, a sell stop at or below it. Take-profit orders trigger when it
+/// moves in favour: a buy at or below `trigger`, a sell at or above it.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum TriggerKind {
+	/// Rest on the book at the last traded price.
+	StopMarket,
+	/// Rest on the book at `limit`.
+	StopLimit,
+	/// Rest on the book at `limit`.
+	TakeProfit,
+}
+
+/// A conditional order. Its escrow and deposit are held from placement, exactly as for
+/// a buy or sell order on the same side.
+#[derive(Encode, Decode, PartialEq, Eq)]
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct TriggerOrderInfo<AccountId, Balance, BlockNumber> {
+	order_id: TriggerOrderIndex,
+	market: Market,
+	owner: AccountId,
+	side: OrderSide,
+	kind: TriggerKind,
+	trigger: Balance,
+	limit: Balance,
+	volume: Balance,
+	deposit: Balance,
+	created: BlockNumber
+}
+
+
+/// Price and volume of one market over `CandlePeriod` blocks starting at `start`.
+/// Prices are trade ratios and `volume` is in the target token.
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+pub struct Candle<Balance, BlockNumber> {
+	pub start: BlockNumber,
+	pub open: Balance,
+	pub high: Balance,
+	pub low: Balance,
+	pub close: Balance,
+	pub volume: Balance,
+}
+
+/// Running sum of a market's price over time: `cumulative` grows by the last price
+/// for every block elapsed, up to block `updated`. It wraps on overflow, which the
+/// difference of two observations tolerates.
+#[derive(Encode, Decode, Clone, Default, PartialEq, Eq, RuntimeDebug)]
+pub struct PriceObservation<BlockNumber> {
+	pub updated: BlockNumber,
+	pub cumulative: u128,
+}
+
+/// Source of manipulation-resistant prices for other pallets.
+pub trait Oracle<Key, Price, BlockNumber> {
+	/// Time-weighted average price of `key` over at least the last `window` blocks, or
+	/// `None` if there is not enough history.
+	fn twap(key: Key, window: BlockNumber) -> Option<Price>;
+}
+
+/// Most candles returned by a single `candles` query.
+pub const MAX_CANDLES_PER_QUERY: u32 = 1_000;
+
+/// Storage reads of one matching step at most: a fill moves four token balances,
+/// records the trade and may close both orders.
+const MATCH_STEP_READS: Weight = 80;
+/// Storage writes of one matching step at most.
+const MATCH_STEP_WRITES: Weight = 40;
 
 decl_storage! {
 	trait Store for Module<T: Trait> as Exchange {
 
-		pub AccountOperation get(fn account_operation): AccountIdOf<T>;
-		pub AccountVault get(fn account_vault): AccountIdOf<T>;
+		/// Layout storage is in. Chains that predate it are at `V0`.
+		pub StorageVersion build(|_: &GenesisConfig<T>| Releases::V2): Releases;
+
+		pub AccountOperation get(fn account_operation) config(): AccountIdOf<T>;
+		pub AccountVault get(fn account_vault) config(): AccountIdOf<T>;
 		
-		pub MinimumRatio get(fn minimum_ratio): BalanceOf<T>;
-		pub MinimumVolume get(fn minimum_volume): BalanceOf<T>;
+		/// Limits copied to every pair when it is listed.
+		pub DefaultPairLimits get(fn default_pair_limits) config(): PairLimitsOf<T>;
+		/// Share of each trade leg and cancelled remainder taken as a fee, 0.1% until set.
+		pub TradeFee get(fn trade_fee): Permill = Permill::from_parts(1_000);
+		pub PairLimit get(fn pair_limits): 
+			map hasher(blake2_128_concat) Market => PairLimitsOf<T>;
+		/// Lowest KYC level an account needs to place orders on a market, zero for none.
+		pub RequiredKyc get(fn required_kyc): 
+			map hasher(blake2_128_concat) Market => u8;
 
 		pub Pair get(fn pair): 
 			map hasher(blake2_128_concat) PairIndex => Option<PairInfoOf<T>>;
@@ -168,41 +402,95 @@ decl_storage! {
 			map hasher(blake2_128_concat) PairIndex => PairNativeInfoOf<T>;
 		pub PairNativeCount get(fn pair_native_count): PairIndex;			
 
+		pub PairProposal get(fn pair_proposal): 
+			map hasher(blake2_128_concat) PairProposalIndex => Option<PairProposalInfoOf<T>>;
+		/// Proposals by the block their challenge period ends in.
+		pub PairProposalQueue get(fn pair_proposal_queue): 
+			double_map hasher(twox_64_concat) T::BlockNumber, hasher(twox_64_concat) PairProposalIndex => ();
+		pub PairProposalCount get(fn pair_proposal_count): PairProposalIndex;
+
 		pub BuyOrder get(fn buy_order): 
 			map hasher(blake2_128_concat) (PairIndex, BuyOrderIndex) => BuyOrderInfoOf<T>;
 		pub BuyOrderList get(fn buy_order_list): 
-			map hasher(blake2_128_concat) PairIndex => Vec<BuyOrderIndex>;
+			double_map hasher(blake2_128_concat) PairIndex, hasher(twox_64_concat) BuyOrderIndex => ();
 		pub BuyOrderUserList get(fn buy_order_user_list): 
-			map hasher(blake2_128_concat) (PairIndex, AccountIdOf<T>) => Vec<BuyOrderIndex>;			
+			double_map hasher(blake2_128_concat) (PairIndex, AccountIdOf<T>), hasher(twox_64_concat) BuyOrderIndex => ();			
 		pub BuyOrderCount get(fn buy_order_count): 
 			map hasher(blake2_128_concat) PairIndex => BuyOrderIndex;
+		pub BuyOrderOpenCount get(fn buy_order_open_count): 
+			map hasher(blake2_128_concat) PairIndex => u32;
 
 		pub BuyOrderNative get(fn buy_order_native): 
 			map hasher(blake2_128_concat) (PairNativeIndex, BuyOrderNativeIndex) => BuyOrderNativeInfoOf<T>;
 		pub BuyOrderNativeList get(fn buy_order_native_list): 
-			map hasher(blake2_128_concat) PairNativeIndex => Vec<BuyOrderNativeIndex>;
+			double_map hasher(blake2_128_concat) PairNativeIndex, hasher(twox_64_concat) BuyOrderNativeIndex => ();
 		pub BuyOrderNativeUserList get(fn buy_order_native_user_list): 
-			map hasher(blake2_128_concat) (PairNativeIndex, AccountIdOf<T>) => Vec<BuyOrderNativeIndex>;				
+			double_map hasher(blake2_128_concat) (PairNativeIndex, AccountIdOf<T>), hasher(twox_64_concat) BuyOrderNativeIndex => ();				
 		pub BuyOrderNativeCount get(fn buy_order_native_count): 
 			map hasher(blake2_128_concat) PairNativeIndex => BuyOrderNativeIndex;
+		pub BuyOrderNativeOpenCount get(fn buy_order_native_open_count): 
+			map hasher(blake2_128_concat) PairNativeIndex => u32;
 
 		pub SellOrder get(fn sell_order): 
 			map hasher(blake2_128_concat) (PairIndex, SellOrderIndex) => SellOrderInfoOf<T>;
 		pub SellOrderList get(fn sell_order_list): 
-			map hasher(blake2_128_concat) PairIndex => Vec<SellOrderIndex>;
+			double_map hasher(blake2_128_concat) PairIndex, hasher(twox_64_concat) SellOrderIndex => ();
 		pub SellOrderUserList get(fn sell_order_user_list): 
-			map hasher(blake2_128_concat) (PairIndex, AccountIdOf<T>) => Vec<SellOrderIndex>;				
+			double_map hasher(blake2_128_concat) (PairIndex, AccountIdOf<T>), hasher(twox_64_concat) SellOrderIndex => ();				
 		pub SellOrderCount get(fn sell_order_count): 
 			map hasher(blake2_128_concat) PairIndex => SellOrderIndex;
+		pub SellOrderOpenCount get(fn sell_order_open_count): 
+			map hasher(blake2_128_concat) PairIndex => u32;
 
 		pub SellOrderNative get(fn sell_order_native): 
 			map hasher(blake2_128_concat) (PairNativeIndex, SellOrderNativeIndex) => SellOrderNativeInfoOf<T>;
 		pub SellOrderNativeList get(fn sell_order_native_list): 
-			map hasher(blake2_128_concat) PairNativeIndex => Vec<SellOrderNativeIndex>;
+			double_map hasher(blake2_128_concat) PairNativeIndex, hasher(twox_64_concat) SellOrderNativeIndex => ();
 		pub SellOrderNativeUserList get(fn sell_order_native_user_list): 
-			map hasher(blake2_128_concat) (PairNativeIndex, AccountIdOf<T>) => Vec<SellOrderNativeIndex>;				
+			double_map hasher(blake2_128_concat) (PairNativeIndex, AccountIdOf<T>), hasher(twox_64_concat) SellOrderNativeIndex => ();				
 		pub SellOrderNativeCount get(fn sell_order_native_count): 
 			map hasher(blake2_128_concat) PairNativeIndex => SellOrderNativeIndex;
+		pub SellOrderNativeOpenCount get(fn sell_order_native_open_count): 
+			map hasher(blake2_128_concat) PairNativeIndex => u32;
+
+		pub OpenOrders get(fn open_orders): 
+			map hasher(blake2_128_concat) AccountIdOf<T> => u32;
+
+		pub TriggerOrder get(fn trigger_order): 
+			double_map hasher(blake2_128_concat) Market, hasher(twox_64_concat) TriggerOrderIndex => Option<TriggerOrderInfoOf<T>>;
+		pub TriggerOrderUserList get(fn trigger_order_user_list): 
+			double_map hasher(blake2_128_concat) AccountIdOf<T>, hasher(twox_64_concat) TriggerOrderIndex => ();
+		pub TriggerOrderCount get(fn trigger_order_count): TriggerOrderIndex;
+		/// Trigger orders waiting on each market.
+		pub TriggerOrderOpenCount get(fn trigger_order_open_count):
+			map hasher(blake2_128_concat) Market => u32;
+
+		/// Open orders on each side of a market as `(ratio, order_id)`, best price first:
+		/// highest ratio for buys, lowest for sells, oldest first at the same ratio.
+		pub OrderBook get(fn order_book):
+			double_map hasher(blake2_128_concat) Market, hasher(twox_64_concat) OrderSide => Vec<(BalanceOf<T>, u128)>;
+		/// Markets whose books may cross, in the order they are matched.
+		pub MarketsToMatch get(fn markets_to_match): Vec<Market>;
+
+		/// Ratio of the most recent trade on each market.
+		pub LastPrice get(fn last_price): 
+			map hasher(blake2_128_concat) Market => Option<BalanceOf<T>>;
+		/// Markets that traded since their trigger orders were last checked.
+		pub UpdatedMarkets get(fn updated_markets): Vec<Market>;
+
+		/// Candles of each market keyed by their first block. Periods without trades have no candle.
+		pub Candles get(fn candle): 
+			double_map hasher(blake2_128_concat) Market, hasher(twox_64_concat) T::BlockNumber => Option<CandleOf<T>>;
+
+		/// Price accumulator of each market as of its latest trade.
+		pub PriceCumulative get(fn price_cumulative): 
+			map hasher(blake2_128_concat) Market => PriceObservationOf<T>;
+		/// Ring buffer of past accumulator values, `OracleObservations` slots per market.
+		pub PriceObservations get(fn price_observation): 
+			double_map hasher(blake2_128_concat) Market, hasher(twox_64_concat) u32 => Option<PriceObservationOf<T>>;
+		/// Slot of the latest entry in `PriceObservations`.
+		pub PriceObservationSlot get(fn price_observation_slot): 
+			map hasher(blake2_128_concat) Market => u32;
 
 		pub Trades get(fn trades): 
 			map hasher(blake2_128_concat) (PairIndex, TradeIndex) => Option<TradeInfoOf<T>>;
@@ -218,12 +506,28 @@ decl_storage! {
 		pub TradeNativeCount get(fn trade_native_count): 
 			map hasher(blake2_128_concat) PairNativeIndex => TradeNativeIndex;		
 	}
+	add_extra_genesis {
+		/// Token pairs to list, as `(base, target)`. The token pallet has no genesis tokens,
+		/// so these are ids of tokens yet to be created and only checked to differ.
+		config(pairs): Vec<(u32, u32)>;
+		/// Target tokens of native pairs to list.
+		config(native_pairs): Vec<u32>;
+		build(|config: &GenesisConfig<T>| {
+			for (base, target) in &config.pairs {
+				assert!(base != target, "genesis pair of a token with itself");
+				Module::<T>::list_pair(*base, *target, config.account_operation.clone());
+			}
+			for target in &config.native_pairs {
+				Module::<T>::list_native_pair(*target, config.account_operation.clone());
+			}
+		});
+	}
 }
 
 decl_event! {
 	pub enum Event<T> where
 		Balance = BalanceOf<T>,
-		//AccountId = <T as system::Trait>::AccountId,
+		AccountId = <T as system::Trait>::AccountId,
 		<T as system::Trait>::BlockNumber,
 	{
 		/// Pair successfully created. \[pair_id, block_number\]
@@ -234,18 +538,38 @@ decl_event! {
 		PairNativeMutated(PairIndex),				
 		/// Pair is paused/unpause trading. \[pair_id, pause\]
 		PairPaused(PairIndex, bool),
-		/// Buy order successfully created. \[order_id, pair_id, ratio, volume\]
+		/// Buy order successfully created. \[order_id, pair_id\]
 		BuyOrderCreated(BuyOrderIndex, PairIndex),
-		/// Buy native order successfully created. \[order_id, pair_id, ratio, volume\]
+		/// Buy native order successfully created. \[order_id, pair_id\]
 		BuyOrderNativeCreated(BuyOrderIndex, PairIndex),		
-		/// Sell order successfully created. \[order_id, pair_id, ratio, volume\]
+		/// Sell order successfully created. \[order_id, pair_id\]
 		SellOrderCreated(SellOrderIndex, PairIndex),
-		/// Sell native order successfully created. \[order_id, pair_id, ratio, volume\]
+		/// Sell native order successfully created. \[order_id, pair_id\]
 		SellOrderNativeCreated(SellOrderIndex, PairIndex),		
 		/// Trade successfully created. \[trade_id, pair_id, ratio, volume\]
 		TradeCreated(TradeIndex, PairIndex, Balance, Balance),
 		/// Trade successfully created. \[trade_id, pair_id, ratio, volume\]
 		TradeNativeCreated(TradeIndex, PairIndex, Balance, Balance),	
+		/// Trigger order placed. \[trigger_id, market\]
+		TriggerOrderCreated(TriggerOrderIndex, Market),
+		/// Trigger order crossed and moved to the book. \[trigger_id, market, order_id, ratio\]
+		TriggerOrderActivated(TriggerOrderIndex, Market, u128, Balance),
+		/// Trigger order cancelled and refunded. \[trigger_id, market\]
+		TriggerOrderCancelled(TriggerOrderIndex, Market),
+		/// Exchange account changed. \[account, account_id\]
+		AccountSet(ExchangeAccount, AccountId),
+		/// Order limits of a pair changed. \[market, limits\]
+		PairLimitsSet(Market, PairLimits<Balance>),
+		/// KYC level required to trade on a market changed. \[market, level\]
+		RequiredKycSet(Market, u8),
+		/// Exchange parameter changed. \[param\]
+		ParamSet(ExchangeParam<Balance>),
+		/// Pair proposed and bond reserved. \[proposal_id, proposer, activates\]
+		PairProposed(PairProposalIndex, AccountId, BlockNumber),
+		/// Proposal rejected and its bond slashed to the vault. \[proposal_id, bond\]
+		PairProposalRejected(PairProposalIndex, Balance),
+		/// Challenge period passed, pair listed and bond refunded. \[proposal_id, market\]
+		PairProposalActivated(PairProposalIndex, Market),
 				
 	}
 }
@@ -263,9 +587,37 @@ decl_error! {
 
 		BelowMinimumVolume,
 		BelowMinimumRatio,
+		/// Order value is below the pair's minimum notional
+		BelowMinimumNotional,
+		/// Ratio is above the pair's maximum price
+		AboveMaximumRatio,
 		
 		NotExchangeAccount,
 		NotOrderCreator,
+		/// Order is not open on this pair
+		OrderNotFound,
+		/// Account already has the maximum number of open orders
+		TooManyOpenOrders,
+		/// This side of the pair already holds the maximum number of orders
+		OrderBookFull,
+		/// Pair does not exist
+		PairNotFound,
+		/// Trigger price must be non-zero
+		InvalidTrigger,
+		/// Last price has already crossed the trigger
+		TriggerAlreadyReached,
+		/// Market already holds the maximum number of trigger orders
+		TooManyTriggers,
+		/// Token does not exist
+		TokenNotFound,
+		/// Base and target are the same token
+		SameToken,
+		/// Pair proposal does not exist or is no longer pending
+		ProposalNotFound,
+		/// Account is frozen or not whitelisted for a token of the pair, so could not be paid out
+		CannotReceiveToken,
+		/// Account's KYC level is below the one the market requires
+		InsufficientKyc,
 
 	}
 }
@@ -275,35 +627,44 @@ decl_module! {
 		fn deposit_event() = default;
 
 		type Error = Error<T>;		
+
+		const MaxOpenOrdersPerUser: u32 = T::MaxOpenOrdersPerUser::get();
+		const MaxOrdersPerSide: u32 = T::MaxOrdersPerSide::get();
+		const MaxTriggersPerMarket: u32 = T::MaxTriggersPerMarket::get();
+		const MaxMatchingSteps: u32 = T::MaxMatchingSteps::get();
+		const OrderDeposit: BalanceOf<T> = T::OrderDeposit::get();
+		const CandlePeriod: T::BlockNumber = T::CandlePeriod::get();
+		const VolumeWindow: T::BlockNumber = T::VolumeWindow::get();
+		const OracleGranularity: T::BlockNumber = T::OracleGranularity::get();
+		const OracleObservations: u32 = T::OracleObservations::get();
 		
 		#[weight = 10_000]
 		fn set_accounts(
 			origin, 
-			account_type:u32, 
+			account: ExchangeAccount, 
 			account_id:AccountIdOf<T>
 		) {
-			ensure_root(origin)?;
-			if account_type == 1 {
-				<AccountOperation<T>>::put(account_id)
-			} else if account_type == 2 {
-				<AccountVault<T>>::put(account_id)
+			T::AdminOrigin::ensure_origin(origin)?;
+			match account {
+				ExchangeAccount::Operation => <AccountOperation<T>>::put(&account_id),
+				ExchangeAccount::Vault => <AccountVault<T>>::put(&account_id),
 			}
+			Self::deposit_event(RawEvent::AccountSet(account, account_id));
 		}
 
 		#[weight = 10_000]
-		fn set_fees(
+		fn set_params(
 			origin, 
-			fee_type:u32, 
-			fee:BalanceOf<T>
+			params: Vec<ExchangeParam<BalanceOf<T>>>
 		) {
-			let caller = ensure_signed(origin)?;
-			let exchange_account = <AccountOperation<T>>::get();
-			ensure!(caller == exchange_account, Error::<T>::NotExchangeAccount);
+			T::FeeOrigin::ensure_origin(origin)?;
 			
-			if fee_type == 1 {
-				<MinimumVolume<T>>::put(fee)
-			} else if fee_type == 2 {
-				<MinimumRatio<T>>::put(fee)
+			for param in params {
+				match &param {
+					ExchangeParam::DefaultPairLimits(limits) => <DefaultPairLimits<T>>::put(limits),
+					ExchangeParam::TradeFee(fee) => <TradeFee>::put(fee),
+				}
+				Self::deposit_event(RawEvent::ParamSet(param));
 			}
 		}		
 
@@ -313,25 +674,8 @@ decl_module! {
 			base: u32,
 			target: u32
 			) {		
-			let banker = ensure_signed(origin.clone())?;
-			let exchange_account = <AccountOperation<T>>::get();
-			ensure!(banker == exchange_account, Error::<T>::NotExchangeAccount);
-
-			let created = <system::Module<T>>::block_number();
-			let active: bool = true;
-
-			let index = PairCount::get();
-			PairCount::put(index + 1);
-
-			<Pair<T>>::insert(index, PairInfo {
-				base,
-				target,
-				banker,
-				active,
-				created
-			});
-
-			Self::deposit_event(RawEvent::PairCreated(index, created));
+			T::ListingOrigin::ensure_origin(origin)?;
+			Self::insert_pair(base, target, Self::account_operation())?;
 		}	
 
 		#[weight = 10_000]
@@ -339,34 +683,45 @@ decl_module! {
 			origin,
 			target: u32
 			) {
-			let banker = ensure_signed(origin)?;
-			let exchange_account = <AccountOperation<T>>::get();
-			ensure!(banker == exchange_account, Error::<T>::NotExchangeAccount);
-			let created = <system::Module<T>>::block_number();
-			let active: bool = true;
-
-			let index = PairNativeCount::get();
-			PairNativeCount::put(index + 1);
-
-			<PairNative<T>>::insert(index, PairNativeInfo {
-				target: target,
-				banker: banker,
-				active: active,
-				created: created
-			});
-
-			Self::deposit_event(RawEvent::PairNativeCreated(index, created));
+			T::ListingOrigin::ensure_origin(origin)?;
+			Self::insert_native_pair(target, Self::account_operation())?;
 		}	
 		
+		#[weight = 10_000]
+		fn set_pair_limits(
+			origin,
+			market: Market,
+			limits: PairLimitsOf<T>
+			) {
+			T::ListingOrigin::ensure_origin(origin)?;
+			ensure!(Self::market_exists(market), Error::<T>::PairNotFound);
+			<PairLimit<T>>::insert(market, limits.clone());
+			Self::deposit_event(RawEvent::PairLimitsSet(market, limits));
+		}
+
+		/// Require traders to hold at least KYC `level` to place orders on `market`, zero to
+		/// lift it. Orders already on the book are left alone.
+		#[weight = 10_000]
+		fn set_required_kyc(
+			origin,
+			market: Market,
+			level: u8
+			) {
+			T::ListingOrigin::ensure_origin(origin)?;
+			ensure!(Self::market_exists(market), Error::<T>::PairNotFound);
+			<RequiredKyc>::insert(market, level);
+			Self::deposit_event(RawEvent::RequiredKycSet(market, level));
+		}
+
 		#[weight = 10_000]
 		fn edit_native_pair(
 			origin,
 			pair_index: u128,			
 			target: u32
 			) {
-			let banker = ensure_signed(origin)?;
-			let exchange_account = <AccountOperation<T>>::get();
-			ensure!(banker == exchange_account, Error::<T>::NotExchangeAccount);
+			T::ListingOrigin::ensure_origin(origin)?;
+			ensure!(<PairNative<T>>::contains_key(pair_index), Error::<T>::PairNotFound);
+			Self::ensure_listable(None, target)?;
 
 			let pair = <PairNative<T>>::get(pair_index);
 			let pair_banker = pair.banker;
@@ -383,6 +738,48 @@ decl_module! {
 			Self::deposit_event(RawEvent::PairNativeMutated(pair_index));
 		}		
 	
+		/// Propose a token pair, reserving `ListingBond`. It is listed with the caller
+		/// as banker once `ListingChallengePeriod` passes without a rejection.
+		#[weight = 10_000]
+		fn propose_pair(
+			origin,
+			base: u32,
+			target: u32
+			) {
+			let proposer = ensure_signed(origin)?;
+			Self::propose_listing(proposer, Some(base), target)?;
+		}
+
+		/// Propose a native pair, as `propose_pair`.
+		#[weight = 10_000]
+		fn propose_native_pair(
+			origin,
+			target: u32
+			) {
+			let proposer = ensure_signed(origin)?;
+			Self::propose_listing(proposer, None, target)?;
+		}
+
+		#[weight = 10_000]
+		fn reject_pair(
+			origin,
+			proposal_id: PairProposalIndex
+			) {
+			T::ListingOrigin::ensure_origin(origin)?;
+			let proposal = Self::pair_proposal(proposal_id).ok_or(Error::<T>::ProposalNotFound)?;
+
+			<PairProposal<T>>::remove(proposal_id);
+			<PairProposalQueue<T>>::remove(proposal.activates, proposal_id);
+			let _ = <T as Trait>::Currency::repatriate_reserved(
+				&proposal.proposer,
+				&Self::account_vault(),
+				proposal.bond,
+				BalanceStatus::Free
+			);
+
+			Self::deposit_event(RawEvent::PairProposalRejected(proposal_id, proposal.bond));
+		}
+
 		#[weight = 10_000]
 		fn buy(
 			origin,
@@ -390,50 +787,7 @@ decl_module! {
 			volume: BalanceOf<T>,
 			ratio: BalanceOf<T>) {
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let caller2 = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			let base = <Pair<T>>::get(pair).unwrap().base;		
-			let volume = volume;
-			let ratio = ratio;
-
-			let exchange = Self::account_operation();
-
-			let base_balance = <Token::Module<T>>::get_balance(base, caller.clone());				
-			ensure!(base_balance >= volume, Error::<T>::InsufficientAmount);
-
-			let minimum_volume = Self::minimum_volume();
-			ensure!(volume>= minimum_volume, Error::<T>::BelowMinimumVolume);
-
-			let minimum_ratio = Self::minimum_ratio();
-			ensure!(ratio>= minimum_ratio, Error::<T>::BelowMinimumRatio);			
-
-			let _volume = volume;
-			let _ratio = ratio;	
-
-			<Token::Module<T>>::transfer_(base, caller.clone(), exchange.clone(), _volume.clone());					
-
-			let index = <BuyOrderCount>::get(pair);			
-
-			<BuyOrder<T>>::insert((pair, index), BuyOrderInfo {
-				order_id: index,
-				pair,
-				buyer: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-			
-			let mut buy_order_list = <BuyOrderList>::get(pair);
-			buy_order_list.insert(buy_order_list.len(), index);
-			<BuyOrderList>::insert(pair, buy_order_list);
-
-			let mut buy_order_user_list = <BuyOrderUserList<T>>::get((pair, caller2.clone()));
-			buy_order_user_list.insert(buy_order_user_list.len(), index);
-			<BuyOrderUserList<T>>::insert((pair, caller2.clone()), buy_order_user_list);
-
-			<BuyOrderCount>::insert(pair, index + 1);
-			//Self::deposit_event(RawEvent::BuyOrderCreated(index, pair, _ratio, _volume));
+			Self::do_buy(creator, pair, volume, ratio)?;
 		}	
 
 		#[weight = 10_000]
@@ -443,47 +797,7 @@ decl_module! {
 			volume: BalanceOf<T>,
 			ratio: BalanceOf<T>) {
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let caller2 = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			
-			let volume = volume;
-			let ratio = ratio;
-
-			let exchange = Self::account_operation();
-			
-
-			let _volume = volume;
-			let _ratio = ratio;	
-
-			Self::transfer_coin(caller.clone(), exchange.clone(), _volume.clone());		
-			
-			let minimum_volume = Self::minimum_volume();
-			ensure!(volume>= minimum_volume, Error::<T>::BelowMinimumVolume);		
-			
-			let minimum_ratio = Self::minimum_ratio();
-			ensure!(ratio>= minimum_ratio, Error::<T>::BelowMinimumRatio);				
-
-			let index = <BuyOrderNativeCount>::get(pair);			
-
-			<BuyOrderNative<T>>::insert((pair, index), BuyOrderNativeInfo {
-				order_id: index,
-				pair,
-				buyer: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-
-			let mut buy_order_list = <BuyOrderNativeList>::get(pair);
-			buy_order_list.insert(buy_order_list.len(), index);			
-			<BuyOrderNativeList>::insert(pair, buy_order_list);
-
-			let mut buy_order_user_list = <BuyOrderNativeUserList<T>>::get((pair, caller2.clone()));
-			buy_order_user_list.insert(buy_order_user_list.len(), index);
-			<BuyOrderNativeUserList<T>>::insert((pair, caller2.clone()), buy_order_user_list);
-
-			<BuyOrderNativeCount>::insert(pair, index + 1);
+			Self::do_buy_native(creator, pair, volume, ratio)?;
 		}			
 	
 		#[weight = 10_000]
@@ -494,48 +808,7 @@ decl_module! {
 			ratio: BalanceOf<T>) {
 
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let caller2 = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			let target = <Pair<T>>::get(pair).unwrap().target;	
-			let volume = volume;
-			let ratio = ratio;					
-			let exchange = Self::account_operation();
-			let target_balance = <Token::Module<T>>::get_balance(target, caller.clone());			
-
-			ensure!(target_balance >= volume, Error::<T>::InsufficientAmount);
-
-			let minimum_volume = Self::minimum_volume();
-			ensure!(volume>= minimum_volume, Error::<T>::BelowMinimumVolume);	
-			
-			let minimum_ratio = Self::minimum_ratio();
-			ensure!(ratio>= minimum_ratio, Error::<T>::BelowMinimumRatio);				
-
-			let _volume = volume;
-			let _ratio = ratio;	
-			
-			<Token::Module<T>>::transfer_(target, caller.clone(), exchange.clone(), _volume);		
-
-			let index = <SellOrderCount>::get(pair);		
-			<SellOrder<T>>::insert((pair, index), SellOrderInfo {
-				order_id: index,
-				pair,
-				seller: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-			
-			let mut sell_order_list = <SellOrderList>::get(pair);
-			sell_order_list.insert(sell_order_list.len(), index);			
-			<SellOrderList>::insert(pair, sell_order_list);
-
-			let mut sell_order_user_list = <SellOrderUserList<T>>::get((pair, caller2.clone()));
-			sell_order_user_list.insert(sell_order_user_list.len(), index);
-			<SellOrderUserList<T>>::insert((pair, caller2.clone()), sell_order_user_list);			
-
-			<SellOrderCount>::insert(pair, index + 1);	
-			//Self::deposit_event(RawEvent::SellOrderCreated(index, pair, _ratio, _volume));
+			Self::do_sell(creator, pair, volume, ratio)?;
 		}	
 		
 		#[weight = 10_000]
@@ -546,47 +819,7 @@ decl_module! {
 			ratio: BalanceOf<T>) {
 
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let caller2 = creator.clone();
-			let created = <system::Module<T>>::block_number();	
-			let target = <PairNative<T>>::get(pair).target;	
-			let volume = volume;
-			let ratio = ratio;					
-			let exchange = Self::account_operation();
-			let target_balance = <Token::Module<T>>::get_balance(target, caller.clone());			
-
-			ensure!(target_balance >= volume, Error::<T>::InsufficientAmount);
-
-			let minimum_volume = Self::minimum_volume();
-			ensure!(volume>= minimum_volume, Error::<T>::BelowMinimumVolume);	
-
-			let minimum_ratio = Self::minimum_ratio();
-			ensure!(ratio>= minimum_ratio, Error::<T>::BelowMinimumRatio);				
-
-			let _volume = volume;
-			let _ratio = ratio;	
-			
-			<Token::Module<T>>::transfer_(target, caller.clone(), exchange.clone(), _volume);		
-
-			let index = <SellOrderNativeCount>::get(pair);		
-			<SellOrderNative<T>>::insert((pair, index), SellOrderNativeInfo {
-				order_id: index,
-				pair,
-				seller: caller,
-				volume: _volume,
-				ratio: _ratio,
-				created
-			});
-			
-			let mut sell_order_list = <SellOrderNativeList>::get(pair);
-			sell_order_list.insert(sell_order_list.len(), index);			
-			<SellOrderNativeList>::insert(pair, sell_order_list);			
-
-			let mut sell_order_user_list = <SellOrderNativeUserList<T>>::get((pair, caller2.clone()));
-			sell_order_user_list.insert(sell_order_user_list.len(), index);
-			<SellOrderNativeUserList<T>>::insert((pair, caller2.clone()), sell_order_user_list);			
-			
-			<SellOrderNativeCount>::insert(pair, index + 1);	
+			Self::do_sell_native(creator, pair, volume, ratio)?;
 		}		
 		
 		#[weight = 10_000]
@@ -595,55 +828,8 @@ decl_module! {
 			pair: PairIndex,
 			buy_order_id: BuyOrderIndex
 		) {
-
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let buy_item = <BuyOrder<T>>::get((pair, buy_order_id));
-			let buy_order_id = buy_item.order_id;
-			let buy_volume = buy_item.volume;			
-			let buyer = buy_item.buyer;	
-			ensure!(creator == buyer, Error::<T>::NotOrderCreator);	
-			let base = <Pair<T>>::get(pair).unwrap().base;
-			let exchange = Self::account_operation();
-			let vault = Self::account_vault();
-			let p999 = sp_runtime::Permill::from_parts(999000);
-	
-			let base_after_fee = p999 * buy_volume;		
-			let volume_buyer = base_after_fee;
-			let volume_vault = buy_volume - volume_buyer;
-		
-			let mut buy_order_list = <BuyOrderList>::get(pair);
-
-			match buy_order_list.binary_search(&buy_order_id) {
-
-				Ok(index) => {
-					buy_order_list.remove(index);
-					<BuyOrderList>::insert(pair, buy_order_list);
-
-					<BuyOrder<T>>::remove((pair, buy_order_id));	
-		
-					<Token::Module<T>>::transfer_(base, exchange.clone(), caller.clone(), volume_buyer);							
-					<Token::Module<T>>::transfer_(base, exchange.clone(), vault.clone(), volume_vault);							
-				},
-				Err(_) => {
-					
-				},
-			}
-
-			let mut buy_order_user_list = <BuyOrderUserList<T>>::get((pair, buyer.clone()));
-
-			match buy_order_user_list.binary_search(&buy_order_id) {
-
-				Ok(index) => {
-					buy_order_user_list.remove(index);
-					<BuyOrderUserList<T>>::insert((pair, buyer.clone()), buy_order_user_list);
-				},
-				Err(_) => {
-					
-				},
-			}			
-
-
+			Self::do_cancel_buy(creator, pair, buy_order_id)?;
 		}	
 		
 		#[weight = 10_000]
@@ -653,50 +839,7 @@ decl_module! {
 			buy_order_id: BuyOrderNativeIndex
 		) {
 			let creator = ensure_signed(origin)?;
-			let buy_item = <BuyOrderNative<T>>::get((pair, buy_order_id));
-			let buy_order_id = buy_item.order_id;
-			let buy_volume = buy_item.volume;			
-			let buyer = buy_item.buyer;		
-			ensure!(creator == buyer, Error::<T>::NotOrderCreator);
-			let exchange = Self::account_operation();
-			let vault = Self::account_vault();
-			let p999 = sp_runtime::Permill::from_parts(999000);
-	
-			let base_after_fee = p999 * buy_volume;		
-			let volume_buyer = base_after_fee;
-			let volume_vault = buy_volume - volume_buyer;
-		
-			let mut buy_order_list = <BuyOrderNativeList>::get(pair);
-
-			match buy_order_list.binary_search(&buy_order_id) {
-
-				Ok(index) => {
-					buy_order_list.remove(index);
-					<BuyOrderNativeList>::insert(pair, buy_order_list);
-
-					<BuyOrderNative<T>>::remove((pair, buy_order_id));	
-
-					Self::transfer_coin(exchange.clone(), buyer.clone(), volume_buyer);
-					Self::transfer_coin(exchange.clone(), vault.clone(), volume_vault);											
-				},
-				Err(_) => {
-					
-				},
-			}
-
-			let mut buy_order_user_list = <BuyOrderNativeUserList<T>>::get((pair, buyer.clone()));
-
-			match buy_order_user_list.binary_search(&buy_order_id) {
-
-				Ok(index) => {
-					buy_order_user_list.remove(index);
-					<BuyOrderNativeUserList<T>>::insert((pair, buyer), buy_order_user_list);
-				},
-				Err(_) => {
-					
-				},
-			}			
-
+			Self::do_cancel_native_buy(creator, pair, buy_order_id)?;
 		}	
 		
 		#[weight = 10_000]
@@ -706,53 +849,7 @@ decl_module! {
 			sell_order_id: SellOrderIndex
 		) {
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let sell_item = <SellOrder<T>>::get((pair, sell_order_id));
-			let sell_order_id = sell_item.order_id;
-			let sell_volume = sell_item.volume;			
-			let seller = sell_item.seller;		
-			ensure!(creator == seller, Error::<T>::NotOrderCreator );	
-			let target = <Pair<T>>::get(pair).unwrap().target;		
-			let exchange = Self::account_operation();
-			let vault = Self::account_vault();
-			let p999 = sp_runtime::Permill::from_parts(999000);
-	
-			let base_after_fee = p999 * sell_volume;		
-			let volume_seller = base_after_fee;
-			let volume_vault = sell_volume - volume_seller;
-		
-			let mut sell_order_list = <SellOrderList>::get(pair);
-
-			match sell_order_list.binary_search(&sell_order_id) {
-
-				Ok(index) => {
-					sell_order_list.remove(index);
-					<SellOrderList>::insert(pair, sell_order_list);
-
-					<SellOrder<T>>::remove((pair, sell_order_id));	
-		
-					<Token::Module<T>>::transfer_(target, exchange.clone(), caller.clone(), volume_seller);							
-					<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), volume_vault);							
-				},
-				Err(_) => {
-					
-				},
-			}
-
-			let mut sell_order_user_list = <SellOrderUserList<T>>::get((pair, seller.clone()));
-
-			match sell_order_user_list.binary_search(&sell_order_id) {
-
-				Ok(index) => {
-					sell_order_user_list.remove(index);
-					<SellOrderUserList<T>>::insert((pair, seller.clone()), sell_order_user_list);
-				},
-				Err(_) => {
-					
-				},
-			}			
-
-
+			Self::do_cancel_sell(creator, pair, sell_order_id)?;
 		}	
 		
 		#[weight = 10_000]
@@ -762,61 +859,108 @@ decl_module! {
 			sell_order_id: SellOrderNativeIndex
 		) {
 			let creator = ensure_signed(origin)?;
-			let caller = creator.clone();
-			let sell_item = <SellOrderNative<T>>::get((pair, sell_order_id));
-			let sell_order_id = sell_item.order_id;
-			let sell_volume = sell_item.volume;			
-			let seller = sell_item.seller;	
-			ensure!(creator == seller, Error::<T>::NotOrderCreator );	
-			let target = <PairNative<T>>::get(pair).target;	
-			let exchange = Self::account_operation();
-			let vault = Self::account_vault();
-			let p999 = sp_runtime::Permill::from_parts(999000);
-	
-			let base_after_fee = p999 * sell_volume;		
-			let volume_seller = base_after_fee;
-			let volume_vault = sell_volume - volume_seller;
-		
-			let mut sell_order_list = <SellOrderNativeList>::get(pair);
+			Self::do_cancel_native_sell(creator, pair, sell_order_id)?;
+		}	
 
-			match sell_order_list.binary_search(&sell_order_id) {
+		#[weight = 10_000]
+		#[transactional]
+		fn place_trigger(
+			origin,
+			market: Market,
+			side: OrderSide,
+			kind: TriggerKind,
+			trigger: BalanceOf<T>,
+			limit: BalanceOf<T>,
+			volume: BalanceOf<T>) {
+
+			let owner = ensure_signed(origin)?;
+			let asset = Self::escrow_asset(market, side)?;
+
+			ensure!(!trigger.is_zero(), Error::<T>::InvalidTrigger);
+			if let Some(last) = Self::last_price(market) {
+				ensure!(!Self::is_triggered(side, kind, trigger, last), Error::<T>::TriggerAlreadyReached);
+			}
 
-				Ok(index) => {
-					sell_order_list.remove(index);
-					<SellOrderNativeList>::insert(pair, sell_order_list);
+			let available = match asset {
+				Some(token) => <Token::Module<T>>::free_balance(token, &owner),
+				None => <T as pallet_token::Trait>::Currency::free_balance(&owner)
+					.saturating_sub(T::OrderDeposit::get()),
+			};
+			ensure!(available >= volume, Error::<T>::InsufficientAmount);
+			// A stop-market order has no limit yet, so check it at its trigger.
+			let ratio = if kind == TriggerKind::StopMarket { trigger } else { limit };
+			Self::check_order_limits(market, side, volume, ratio)?;
+
+			Self::ensure_can_settle(market, &owner)?;
+			ensure!(Self::trigger_order_open_count(market) < T::MaxTriggersPerMarket::get(), Error::<T>::TooManyTriggers);
+			Self::open_order_slot(&owner, Self::open_order_count(market, side))?;
+			Self::move_asset(asset, owner.clone(), Self::account_operation(), volume)?;
+
+			let index = <TriggerOrderCount>::get();
+			<TriggerOrder<T>>::insert(market, index, TriggerOrderInfo {
+				order_id: index,
+				market,
+				owner: owner.clone(),
+				side,
+				kind,
+				trigger,
+				limit,
+				volume,
+				deposit: T::OrderDeposit::get(),
+				created: <system::Module<T>>::block_number()
+			});
+			<TriggerOrderUserList<T>>::insert(owner, index, ());
+			<TriggerOrderCount>::put(index + 1);
+			<TriggerOrderOpenCount>::mutate(market, |n| *n += 1);
 
-					<SellOrderNative<T>>::remove((pair, sell_order_id));	
+			Self::deposit_event(RawEvent::TriggerOrderCreated(index, market));
+		}
 
-					<Token::Module<T>>::transfer_(target, exchange.clone(), caller.clone(), volume_seller);							
-					<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), volume_vault);											
-				},
-				Err(_) => {
-					
-				},
-			}
+		#[weight = 10_000]
+		fn cancel_trigger(
+			origin,
+			market: Market,
+			trigger_order_id: TriggerOrderIndex
+		) {
+			let creator = ensure_signed(origin)?;
+			let order = <TriggerOrder<T>>::get(market, trigger_order_id).ok_or(Error::<T>::OrderNotFound)?;
+			ensure!(creator == order.owner, Error::<T>::NotOrderCreator);
 
-			let mut sell_order_user_list = <SellOrderNativeUserList<T>>::get((pair, seller.clone()));
+			let asset = Self::escrow_asset(market, order.side)?;
+			Self::move_asset(asset, Self::account_operation(), order.owner.clone(), order.volume)?;
 
-			match sell_order_user_list.binary_search(&sell_order_id) {
+			<TriggerOrder<T>>::remove(market, trigger_order_id);
+			<TriggerOrderUserList<T>>::remove(&order.owner, trigger_order_id);
+			<TriggerOrderOpenCount>::mutate(market, |n| *n = n.saturating_sub(1));
+			Self::release_order_slot(&order.owner, order.deposit);
 
-				Ok(index) => {
-					sell_order_user_list.remove(index);
-					<SellOrderNativeUserList<T>>::insert((pair, seller.clone()), sell_order_user_list);
-				},
-				Err(_) => {
-					
-				},
-			}			
+			Self::deposit_event(RawEvent::TriggerOrderCancelled(trigger_order_id, market));
+		}
 
-		}			
-		
-							
-		fn on_finalize(now: T::BlockNumber) {
-			
-			let _now = now;
-			Self::match_native_orders();
-			Self::match_orders();
+		fn on_runtime_upgrade() -> Weight {
+			migrations::migrate::<T>()
+		}
 
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			// Listing a due proposal reads the proposal, bond and tokens and writes the pair.
+			let due = <PairProposalQueue<T>>::iter_prefix(now).count() as Weight;
+			T::DbWeight::get().reads_writes(1 + 6 * due, 1 + 6 * due)
+				.saturating_add(Self::matching_weight())
+		}
+
+		fn on_finalize(now: T::BlockNumber) {
+			Self::activate_listings(now);
+
+			// Matching and trigger checks share `MaxMatchingSteps`, charged in
+			// `on_initialize`.
+			let mut steps = T::MaxMatchingSteps::get();
+			Self::match_markets(&mut steps);
+
+			// Trigger orders crossed by this block's trades join the book and are
+			// matched straight away. Trades from this second pass are left in
+			// `UpdatedMarkets` and checked next block.
+			Self::activate_triggers(&mut steps);
+			Self::match_markets(&mut steps);
 		}
 
 	}
@@ -824,34 +968,873 @@ decl_module! {
 
 impl<T: Trait> Module<T> {
 
+	/// Move native currency. The operation and vault accounts hold everyone's escrow and
+	/// fees, so they are never let drop below the existential deposit.
 	fn transfer_coin( 
 		from: AccountIdOf<T>, 
 		to: AccountIdOf<T>, 
-		value:BalanceOf<T>) -> () {
-
-		let _lol = <T as pallet_token::Trait>::Currency::transfer(&from, &to, value, AllowDeath);//.map_err(|_| DispatchError::Other("Can't make transfer"))?;			
+		value:BalanceOf<T>) -> DispatchResult {
+
+		let existence = if from == Self::account_operation() || from == Self::account_vault() {
+			KeepAlive
+		} else {
+			AllowDeath
+		};
+		<T as pallet_token::Trait>::Currency::transfer(&from, &to, value, existence)
 	}	
 
+	/// Enforce the open order limits for `who` and reserve the order deposit.
+	fn open_order_slot(who: &AccountIdOf<T>, open_on_side: u32) -> DispatchResult {
+		ensure!(Self::open_orders(who) < T::MaxOpenOrdersPerUser::get(), Error::<T>::TooManyOpenOrders);
+		ensure!(open_on_side < T::MaxOrdersPerSide::get(), Error::<T>::OrderBookFull);
+
+		<T as Trait>::Currency::reserve(who, T::OrderDeposit::get())?;
+		<OpenOrders<T>>::mutate(who, |n| *n += 1);
+		Ok(())
+	}
+
+	/// Release the deposit of an order that has left the book.
+	fn release_order_slot(who: &AccountIdOf<T>, deposit: BalanceOf<T>) -> () {
+		<T as Trait>::Currency::unreserve(who, deposit);
+		<OpenOrders<T>>::mutate(who, |n| *n = n.saturating_sub(1));
+	}
+
+	/// Whether `market` has been listed.
+	fn market_exists(market: Market) -> bool {
+		match market {
+			Market::Pair(pair) => <Pair<T>>::contains_key(pair),
+			Market::Native(pair) => <PairNative<T>>::contains_key(pair),
+		}
+	}
 
+	/// Token escrowed by `side` of `market`, or `None` for the native currency.
+	fn escrow_asset(market: Market, side: OrderSide) -> Result<Option<TokenIndex>, Error<T>> {
+		match market {
+			Market::Pair(pair) => {
+				let pair = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?;
+				match side {
+					OrderSide::Buy => Ok(Some(pair.base)),
+					OrderSide::Sell => Ok(Some(pair.target)),
+				}
+			},
+			Market::Native(pair) => {
+				ensure!(pair < <PairNativeCount>::get(), Error::<T>::PairNotFound);
+				match side {
+					OrderSide::Buy => Ok(None),
+					OrderSide::Sell => Ok(Some(<PairNative<T>>::get(pair).target)),
+				}
+			},
+		}
+	}
+
+	/// Move `value` of an escrow asset as returned by `escrow_asset`.
+	fn move_asset(asset: Option<TokenIndex>, from: AccountIdOf<T>, to: AccountIdOf<T>, value: BalanceOf<T>) -> DispatchResult {
+		match asset {
+			Some(token) => <Token::Module<T>>::transfer_(token, from, to, value),
+			None => Self::transfer_coin(from, to, value),
+		}
+	}
+
+	/// Refuse orders from `who` if it lacks the KYC level `market` requires, or a token of
+	/// `market` could not be paid out to it, being frozen or restricted to a whitelist `who`
+	/// is not on.
+	fn ensure_can_settle(market: Market, who: &AccountIdOf<T>) -> DispatchResult {
+		ensure!(
+			<T as Token::Trait>::KycProvider::kyc_level(who) >= <RequiredKyc>::get(market),
+			Error::<T>::InsufficientKyc
+		);
+		let tokens = match market {
+			Market::Pair(pair) => {
+				let pair = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?;
+				vec![pair.base, pair.target]
+			},
+			Market::Native(pair) => {
+				ensure!(pair < <PairNativeCount>::get(), Error::<T>::PairNotFound);
+				vec![<PairNative<T>>::get(pair).target]
+			},
+		};
+		for token in tokens {
+			ensure!(<Token::Module<T>>::can_receive(token, who), Error::<T>::CannotReceiveToken);
+		}
+		Ok(())
+	}
+
+	/// Number of orders resting on `side` of `market`.
+	fn open_order_count(market: Market, side: OrderSide) -> u32 {
+		match (market, side) {
+			(Market::Pair(pair), OrderSide::Buy) => <BuyOrderOpenCount>::get(pair),
+			(Market::Pair(pair), OrderSide::Sell) => <SellOrderOpenCount>::get(pair),
+			(Market::Native(pair), OrderSide::Buy) => <BuyOrderNativeOpenCount>::get(pair),
+			(Market::Native(pair), OrderSide::Sell) => <SellOrderNativeOpenCount>::get(pair),
+		}
+	}
+
+	/// Whether a `kind` trigger on `side` at `trigger` fires at price `last`.
+	fn is_triggered(side: OrderSide, kind: TriggerKind, trigger: BalanceOf<T>, last: BalanceOf<T>) -> bool {
+		match (kind, side) {
+			(TriggerKind::TakeProfit, OrderSide::Buy) => last <= trigger,
+			(TriggerKind::TakeProfit, OrderSide::Sell) => last >= trigger,
+			(_, OrderSide::Buy) => last >= trigger,
+			(_, OrderSide::Sell) => last <= trigger,
+		}
+	}
+
+	/// Record a trade of `volume` target at `ratio` on `market`: last price, the
+	/// current candle and the list of markets whose triggers need checking.
+	fn record_trade(market: Market, ratio: BalanceOf<T>, volume: BalanceOf<T>) -> () {
+		Self::accumulate_price(market);
+		<LastPrice<T>>::insert(market, ratio);
+		<UpdatedMarkets>::mutate(|markets| if !markets.contains(&market) {
+			markets.push(market);
+		});
+
+		let start = Self::candle_start(<system::Module<T>>::block_number());
+		<Candles<T>>::mutate(market, start, |candle| match candle {
+			Some(candle) => {
+				candle.high = candle.high.max(ratio);
+				candle.low = candle.low.min(ratio);
+				candle.close = ratio;
+				candle.volume = candle.volume.saturating_add(volume);
+			},
+			None => *candle = Some(Candle {
+				start,
+				open: ratio,
+				high: ratio,
+				low: ratio,
+				close: ratio,
+				volume,
+			}),
+		});
+	}
+
+	fn candle_period() -> T::BlockNumber {
+		T::CandlePeriod::get().max(One::one())
+	}
+
+	/// First block of the candle containing `block`.
+	fn candle_start(block: T::BlockNumber) -> T::BlockNumber {
+		block - block % Self::candle_period()
+	}
+
+	/// Candles of `market` overlapping blocks `from..=to`, oldest first, at most
+	/// `MAX_CANDLES_PER_QUERY` periods from `from`.
+	pub fn candles(market: Market, from: T::BlockNumber, to: T::BlockNumber) -> Vec<CandleOf<T>> {
+		let period = Self::candle_period();
+		let mut start = Self::candle_start(from);
+		let mut candles = Vec::new();
+
+		for _ in 0..MAX_CANDLES_PER_QUERY {
+			if start > to {
+				break;
+			}
+			if let Some(candle) = <Candles<T>>::get(market, start) {
+				candles.push(candle);
+			}
+			start = start.saturating_add(period);
+		}
+		candles
+	}
+
+	/// Target volume traded on `market` over the last `VolumeWindow` blocks, counted
+	/// in whole candles.
+	pub fn volume_24h(market: Market) -> BalanceOf<T> {
+		let now = <system::Module<T>>::block_number();
+		let from = now.saturating_sub(T::VolumeWindow::get()).saturating_add(One::one());
+		Self::candles(market, from, now)
+			.iter()
+			.fold(Zero::zero(), |volume: BalanceOf<T>, candle| volume.saturating_add(candle.volume))
+	}
+
+	/// Accumulator of `market` brought forward to block `now` at the last price.
+	fn cumulative_at(market: Market, now: T::BlockNumber) -> PriceObservationOf<T> {
+		let mut observation = Self::price_cumulative(market);
+		if let Some(last) = Self::last_price(market) {
+			let elapsed: u128 = now.saturating_sub(observation.updated).saturated_into();
+			let last: u128 = last.saturated_into();
+			observation.cumulative = observation.cumulative.wrapping_add(last.wrapping_mul(elapsed));
+		}
+		observation.updated = now;
+		observation
+	}
+
+	/// Bring the accumulator of `market` up to the current block before its price
+	/// changes, and keep an observation every `OracleGranularity` blocks. Only the last
+	/// price of a block is accumulated, so trades within one block cannot move it.
+	fn accumulate_price(market: Market) -> () {
+		let now = <system::Module<T>>::block_number();
+		let observation = Self::cumulative_at(market, now);
+		<PriceCumulative<T>>::insert(market, observation.clone());
+
+		let slots = T::OracleObservations::get().max(1);
+		let slot = Self::price_observation_slot(market);
+		let due = match Self::price_observation(market, slot) {
+			Some(latest) => now >= latest.updated.saturating_add(T::OracleGranularity::get()),
+			None => true,
+		};
+		if due {
+			let next = if Self::price_observation(market, slot).is_some() { (slot + 1) % slots } else { slot };
+			<PriceObservations<T>>::insert(market, next, observation);
+			<PriceObservationSlot>::insert(market, next);
+		}
+	}
+
+	/// Time-weighted average ratio of `market` since the newest observation at least
+	/// `window` blocks old.
+	pub fn twap(market: Market, window: T::BlockNumber) -> Option<BalanceOf<T>> {
+		let now = <system::Module<T>>::block_number();
+		let since = now.checked_sub(&window)?;
+
+		let oldest = <PriceObservations<T>>::iter_prefix(market)
+			.map(|(_, observation)| observation)
+			.filter(|observation| observation.updated <= since)
+			.max_by_key(|observation| observation.updated)?;
+
+		let elapsed: u128 = now.saturating_sub(oldest.updated).saturated_into();
+		if elapsed == 0 {
+			return None;
+		}
+		let current = Self::cumulative_at(market, now);
+		let average = current.cumulative.wrapping_sub(oldest.cumulative) / elapsed;
+		Some(average.saturated_into())
+	}
+
+	/// Move every trigger order on the `UpdatedMarkets` crossed by the last price into
+	/// the book, oldest first, taking one of `steps` per market and per order moved.
+	/// An order whose side of the book is full stays pending until a slot frees up.
+	/// Markets not finished when `steps` run out are checked next block.
+	fn activate_triggers(steps: &mut u32) -> () {
+		let mut markets = <UpdatedMarkets>::get();
+		let mut checked = 0;
+
+		for &market in markets.iter() {
+			if *steps == 0 {
+				break;
+			}
+			*steps -= 1;
+
+			let last = match Self::last_price(market) {
+				Some(last) => last,
+				None => {
+					checked += 1;
+					continue;
+				},
+			};
+
+			let mut triggered: Vec<TriggerOrderInfoOf<T>> = <TriggerOrder<T>>::iter_prefix(market)
+				.map(|(_, order)| order)
+				.filter(|order| Self::is_triggered(order.side, order.kind, order.trigger, last))
+				.collect();
+			triggered.sort_by_key(|order| order.order_id);
+
+			let mut finished = true;
+			for order in triggered {
+				if Self::open_order_count(market, order.side) >= T::MaxOrdersPerSide::get() {
+					continue;
+				}
+				if *steps == 0 {
+					finished = false;
+					break;
+				}
+				*steps -= 1;
+				let ratio = if order.kind == TriggerKind::StopMarket { last } else { order.limit };
+
+				<TriggerOrder<T>>::remove(market, order.order_id);
+				<TriggerOrderUserList<T>>::remove(&order.owner, order.order_id);
+				<TriggerOrderOpenCount>::mutate(market, |n| *n = n.saturating_sub(1));
+
+				let owner = order.owner;
+				let book_order_id = match (market, order.side) {
+					(Market::Pair(pair), OrderSide::Buy) =>
+						Self::insert_buy_order(pair, owner, order.volume, ratio, order.deposit),
+					(Market::Pair(pair), OrderSide::Sell) =>
+						Self::insert_sell_order(pair, owner, order.volume, ratio, order.deposit),
+					(Market::Native(pair), OrderSide::Buy) =>
+						Self::insert_native_buy_order(pair, owner, order.volume, ratio, order.deposit),
+					(Market::Native(pair), OrderSide::Sell) =>
+						Self::insert_native_sell_order(pair, owner, order.volume, ratio, order.deposit),
+				};
+
+				Self::deposit_event(RawEvent::TriggerOrderActivated(order.order_id, market, book_order_id, ratio));
+			}
+
+			if !finished {
+				break;
+			}
+			checked += 1;
+		}
+
+		<UpdatedMarkets>::put(markets.split_off(checked));
+	}
+
+	/// Rest a buy order of `volume` base at `ratio` on `pair` for `creator`.
+	#[transactional]
+	fn do_buy(
+		creator: AccountIdOf<T>,
+		pair: PairIndex,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+	) -> DispatchResult {
+		let base = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.base;
+		let base_balance = <Token::Module<T>>::free_balance(base, &creator);
+		ensure!(base_balance >= volume, Error::<T>::InsufficientAmount);
+
+		Self::check_order_limits(Market::Pair(pair), OrderSide::Buy, volume, ratio)?;
+		Self::ensure_can_settle(Market::Pair(pair), &creator)?;
+		Self::open_order_slot(&creator, <BuyOrderOpenCount>::get(pair))?;
+
+		<Token::Module<T>>::transfer_(base, creator.clone(), Self::account_operation(), volume)?;
+
+		let index = Self::insert_buy_order(pair, creator, volume, ratio, T::OrderDeposit::get());
+		Self::deposit_event(RawEvent::BuyOrderCreated(index, pair));
+		Ok(())
+	}
+
+	/// Rest a buy order of `volume` native currency at `ratio` on native `pair` for `creator`.
+	#[transactional]
+	fn do_buy_native(
+		creator: AccountIdOf<T>,
+		pair: PairNativeIndex,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+	) -> DispatchResult {
+		let native_balance = <T as pallet_token::Trait>::Currency::free_balance(&creator);
+		let needed = volume.checked_add(&T::OrderDeposit::get()).ok_or(Error::<T>::InsufficientAmount)?;
+		ensure!(native_balance >= needed, Error::<T>::InsufficientAmount);
+
+		Self::check_order_limits(Market::Native(pair), OrderSide::Buy, volume, ratio)?;
+		Self::ensure_can_settle(Market::Native(pair), &creator)?;
+		Self::open_order_slot(&creator, <BuyOrderNativeOpenCount>::get(pair))?;
+
+		Self::transfer_coin(creator.clone(), Self::account_operation(), volume)?;
+
+		let index = Self::insert_native_buy_order(pair, creator, volume, ratio, T::OrderDeposit::get());
+		Self::deposit_event(RawEvent::BuyOrderNativeCreated(index, pair));
+		Ok(())
+	}
+
+	/// Rest a sell order of `volume` target at `ratio` on `pair` for `creator`.
+	#[transactional]
+	fn do_sell(
+		creator: AccountIdOf<T>,
+		pair: PairIndex,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+	) -> DispatchResult {
+		let target = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.target;
+		let target_balance = <Token::Module<T>>::free_balance(target, &creator);
+		ensure!(target_balance >= volume, Error::<T>::InsufficientAmount);
+
+		Self::check_order_limits(Market::Pair(pair), OrderSide::Sell, volume, ratio)?;
+		Self::ensure_can_settle(Market::Pair(pair), &creator)?;
+		Self::open_order_slot(&creator, <SellOrderOpenCount>::get(pair))?;
+
+		<Token::Module<T>>::transfer_(target, creator.clone(), Self::account_operation(), volume)?;
+
+		let index = Self::insert_sell_order(pair, creator, volume, ratio, T::OrderDeposit::get());
+		Self::deposit_event(RawEvent::SellOrderCreated(index, pair));
+		Ok(())
+	}
+
+	/// Rest a sell order of `volume` target at `ratio` on native `pair` for `creator`.
+	#[transactional]
+	fn do_sell_native(
+		creator: AccountIdOf<T>,
+		pair: PairNativeIndex,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+	) -> DispatchResult {
+		let target = <PairNative<T>>::get(pair).target;
+		let target_balance = <Token::Module<T>>::free_balance(target, &creator);
+		ensure!(target_balance >= volume, Error::<T>::InsufficientAmount);
+
+		Self::check_order_limits(Market::Native(pair), OrderSide::Sell, volume, ratio)?;
+		Self::ensure_can_settle(Market::Native(pair), &creator)?;
+		Self::open_order_slot(&creator, <SellOrderNativeOpenCount>::get(pair))?;
+
+		<Token::Module<T>>::transfer_(target, creator.clone(), Self::account_operation(), volume)?;
+
+		let index = Self::insert_native_sell_order(pair, creator, volume, ratio, T::OrderDeposit::get());
+		Self::deposit_event(RawEvent::SellOrderNativeCreated(index, pair));
+		Ok(())
+	}
+
+	/// Cancel `creator`'s buy order on `pair` and refund what is left of it.
+	#[transactional]
+	fn do_cancel_buy(
+		creator: AccountIdOf<T>,
+		pair: PairIndex,
+		buy_order_id: BuyOrderIndex,
+	) -> DispatchResult {
+		ensure!(<BuyOrderList>::contains_key(pair, buy_order_id), Error::<T>::OrderNotFound);
+		let mut buy_item = <BuyOrder<T>>::get((pair, buy_order_id));
+		ensure!(creator == buy_item.buyer, Error::<T>::NotOrderCreator);	
+		let base = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.base;
+		let exchange = Self::account_operation();
+		let vault = Self::account_vault();
+		let after_fee = Self::trade_fee().left_from_one();
+
+		let volume_vault = buy_item.volume - after_fee * buy_item.volume;
+		<Token::Module<T>>::transfer_(base, exchange, vault, volume_vault)?;
+		buy_item.volume = buy_item.volume - volume_vault;
+
+		Self::close_buy_order(pair, buy_item)
+	}
+
+	/// Cancel `creator`'s buy order on native `pair` and refund what is left of it.
+	#[transactional]
+	fn do_cancel_native_buy(
+		creator: AccountIdOf<T>,
+		pair: PairNativeIndex,
+		buy_order_id: BuyOrderNativeIndex,
+	) -> DispatchResult {
+		ensure!(<BuyOrderNativeList>::contains_key(pair, buy_order_id), Error::<T>::OrderNotFound);
+		let mut buy_item = <BuyOrderNative<T>>::get((pair, buy_order_id));
+		ensure!(creator == buy_item.buyer, Error::<T>::NotOrderCreator);	
+		let exchange = Self::account_operation();
+		let vault = Self::account_vault();
+		let after_fee = Self::trade_fee().left_from_one();
+
+		let volume_vault = buy_item.volume - after_fee * buy_item.volume;
+		Self::transfer_coin(exchange, vault, volume_vault)?;
+		buy_item.volume = buy_item.volume - volume_vault;
+
+		Self::close_native_buy_order(pair, buy_item)
+	}
+
+	/// Cancel `creator`'s sell order on `pair` and refund what is left of it.
+	#[transactional]
+	fn do_cancel_sell(
+		creator: AccountIdOf<T>,
+		pair: PairIndex,
+		sell_order_id: SellOrderIndex,
+	) -> DispatchResult {
+		ensure!(<SellOrderList>::contains_key(pair, sell_order_id), Error::<T>::OrderNotFound);
+		let mut sell_item = <SellOrder<T>>::get((pair, sell_order_id));
+		ensure!(creator == sell_item.seller, Error::<T>::NotOrderCreator);	
+		let target = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.target;
+		let exchange = Self::account_operation();
+		let vault = Self::account_vault();
+		let after_fee = Self::trade_fee().left_from_one();
+
+		let volume_vault = sell_item.volume - after_fee * sell_item.volume;
+		<Token::Module<T>>::transfer_(target, exchange, vault, volume_vault)?;
+		sell_item.volume = sell_item.volume - volume_vault;
+
+		Self::close_sell_order(pair, sell_item)
+	}
+
+	/// Cancel `creator`'s sell order on native `pair` and refund what is left of it.
+	#[transactional]
+	fn do_cancel_native_sell(
+		creator: AccountIdOf<T>,
+		pair: PairNativeIndex,
+		sell_order_id: SellOrderNativeIndex,
+	) -> DispatchResult {
+		ensure!(<SellOrderNativeList>::contains_key(pair, sell_order_id), Error::<T>::OrderNotFound);
+		let mut sell_item = <SellOrderNative<T>>::get((pair, sell_order_id));
+		ensure!(creator == sell_item.seller, Error::<T>::NotOrderCreator);	
+		let target = <PairNative<T>>::get(pair).target;
+		let exchange = Self::account_operation();
+		let vault = Self::account_vault();
+		let after_fee = Self::trade_fee().left_from_one();
+
+		let volume_vault = sell_item.volume - after_fee * sell_item.volume;
+		<Token::Module<T>>::transfer_(target, exchange, vault, volume_vault)?;
+		sell_item.volume = sell_item.volume - volume_vault;
+
+		Self::close_native_sell_order(pair, sell_item)
+	}
+
+	/// Check a pair of `base`, `None` for the native currency, and `target` can be
+	/// listed: both tokens exist and differ.
+	fn ensure_listable(base: Option<TokenIndex>, target: TokenIndex) -> Result<(), Error<T>> {
+		ensure!(base != Some(target), Error::<T>::SameToken);
+		ensure!(<Token::Tokens<T>>::contains_key(target), Error::<T>::TokenNotFound);
+		if let Some(base) = base {
+			ensure!(<Token::Tokens<T>>::contains_key(base), Error::<T>::TokenNotFound);
+		}
+		Ok(())
+	}
+
+	/// List a token pair of existing, distinct tokens.
+	fn insert_pair(base: u32, target: u32, banker: AccountIdOf<T>) -> Result<PairIndex, Error<T>> {
+		Self::ensure_listable(Some(base), target)?;
+		Ok(Self::list_pair(base, target, banker))
+	}
+
+	/// List a native pair of an existing token.
+	fn insert_native_pair(target: u32, banker: AccountIdOf<T>) -> Result<PairNativeIndex, Error<T>> {
+		Self::ensure_listable(None, target)?;
+		Ok(Self::list_native_pair(target, banker))
+	}
+
+	fn list_pair(base: u32, target: u32, banker: AccountIdOf<T>) -> PairIndex {
+		let created = <system::Module<T>>::block_number();
+		let active: bool = true;
+
+		let index = PairCount::get();
+		PairCount::put(index + 1);
+
+		<Pair<T>>::insert(index, PairInfo {
+			base,
+			target,
+			banker,
+			active,
+			created
+		});
+		<PairLimit<T>>::insert(Market::Pair(index), Self::default_pair_limits());
+
+		Self::deposit_event(RawEvent::PairCreated(index, created));
+		index
+	}
+
+	fn list_native_pair(target: u32, banker: AccountIdOf<T>) -> PairNativeIndex {
+		let created = <system::Module<T>>::block_number();
+		let active: bool = true;
+
+		let index = PairNativeCount::get();
+		PairNativeCount::put(index + 1);
+
+		<PairNative<T>>::insert(index, PairNativeInfo {
+			target: target,
+			banker: banker,
+			active: active,
+			created: created
+		});
+		<PairLimit<T>>::insert(Market::Native(index), Self::default_pair_limits());
+
+		Self::deposit_event(RawEvent::PairNativeCreated(index, created));
+		index
+	}
+
+	/// Check an order of `volume` at `ratio` against the limits of `market`. `volume`
+	/// is in base for buy orders and in target for sell orders.
+	fn check_order_limits(
+		market: Market,
+		side: OrderSide,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+	) -> DispatchResult {
+		let limits = Self::pair_limits(market);
+		ensure!(ratio >= limits.min_price, Error::<T>::BelowMinimumRatio);
+		ensure!(limits.max_price.is_zero() || ratio <= limits.max_price, Error::<T>::AboveMaximumRatio);
+
+		let (amount, notional) = match side {
+			OrderSide::Buy => (Self::target_for_base(volume, ratio), volume),
+			OrderSide::Sell => (volume, Self::base_for_target(volume, ratio)),
+		};
+		ensure!(amount >= limits.min_amount, Error::<T>::BelowMinimumVolume);
+		ensure!(notional >= limits.min_notional, Error::<T>::BelowMinimumNotional);
+		Ok(())
+	}
+
+	fn propose_listing(proposer: AccountIdOf<T>, base: Option<u32>, target: u32) -> DispatchResult {
+		Self::ensure_listable(base, target)?;
+
+		let bond = T::ListingBond::get();
+		<T as Trait>::Currency::reserve(&proposer, bond)?;
+
+		let activates = <system::Module<T>>::block_number() + T::ListingChallengePeriod::get();
+		let index = <PairProposalCount>::get();
+		<PairProposal<T>>::insert(index, PairProposalInfo {
+			proposer: proposer.clone(),
+			base,
+			target,
+			bond,
+			activates,
+		});
+		<PairProposalQueue<T>>::insert(activates, index, ());
+		<PairProposalCount>::put(index + 1);
+
+		Self::deposit_event(RawEvent::PairProposed(index, proposer, activates));
+		Ok(())
+	}
+
+	/// List every proposal whose challenge period ends at `now` and refund its bond. A
+	/// proposal whose token was destroyed in the meantime is dropped.
+	fn activate_listings(now: T::BlockNumber) -> () {
+		let mut due: Vec<PairProposalIndex> = <PairProposalQueue<T>>::iter_prefix(now)
+			.map(|(index, _)| index)
+			.collect();
+		due.sort();
+		<PairProposalQueue<T>>::remove_prefix(now);
+
+		for index in due {
+			let proposal = match <PairProposal<T>>::take(index) {
+				Some(proposal) => proposal,
+				None => continue,
+			};
+			<T as Trait>::Currency::unreserve(&proposal.proposer, proposal.bond);
+
+			let market = match proposal.base {
+				Some(base) => Self::insert_pair(base, proposal.target, proposal.proposer).map(Market::Pair),
+				None => Self::insert_native_pair(proposal.target, proposal.proposer).map(Market::Native),
+			};
+			let market = match market {
+				Ok(market) => market,
+				Err(_) => continue,
+			};
+			Self::deposit_event(RawEvent::PairProposalActivated(index, market));
+		}
+	}
+
+	/// Place an order in the price order of its side of `market`'s `OrderBook` and
+	/// queue the market for matching.
+	fn add_to_book(market: Market, side: OrderSide, ratio: BalanceOf<T>, order_id: u128) -> () {
+		<OrderBook<T>>::mutate(market, side, |book| {
+			let at = book.iter()
+				.position(|&(r, id)| match side {
+					OrderSide::Buy => r < ratio || (r == ratio && id > order_id),
+					OrderSide::Sell => r > ratio || (r == ratio && id > order_id),
+				})
+				.unwrap_or(book.len());
+			book.insert(at, (ratio, order_id));
+		});
+		<MarketsToMatch>::mutate(|markets| if !markets.contains(&market) {
+			markets.push(market);
+		});
+	}
+
+	fn remove_from_book(market: Market, side: OrderSide, order_id: u128) -> () {
+		<OrderBook<T>>::mutate(market, side, |book| book.retain(|&(_, id)| id != order_id));
+	}
+
+	/// Rest a buy order on the book. Its escrow and deposit must already be held.
+	fn insert_buy_order(
+		pair: PairIndex,
+		buyer: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+		deposit: BalanceOf<T>) -> BuyOrderIndex {
+
+		let index = <BuyOrderCount>::get(pair);
+		let created = <system::Module<T>>::block_number();
+
+		<BuyOrder<T>>::insert((pair, index), BuyOrderInfo {
+			order_id: index,
+			pair,
+			buyer: buyer.clone(),
+			volume,
+			ratio,
+			deposit,
+			created
+		});
+
+		<BuyOrderList>::insert(pair, index, ());
+		<BuyOrderUserList<T>>::insert((pair, buyer), index, ());
+
+		<BuyOrderOpenCount>::mutate(pair, |n| *n += 1);
+		<BuyOrderCount>::insert(pair, index + 1);
+		Self::add_to_book(Market::Pair(pair), OrderSide::Buy, ratio, index);
+		index
+	}
+
+	/// Rest a native buy order on the book. Its escrow and deposit must already be held.
+	fn insert_native_buy_order(
+		pair: PairNativeIndex,
+		buyer: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+		deposit: BalanceOf<T>) -> BuyOrderNativeIndex {
+
+		let index = <BuyOrderNativeCount>::get(pair);
+		let created = <system::Module<T>>::block_number();
+
+		<BuyOrderNative<T>>::insert((pair, index), BuyOrderNativeInfo {
+			order_id: index,
+			pair,
+			buyer: buyer.clone(),
+			volume,
+			ratio,
+			deposit,
+			created
+		});
+
+		<BuyOrderNativeList>::insert(pair, index, ());
+		<BuyOrderNativeUserList<T>>::insert((pair, buyer), index, ());
+
+		<BuyOrderNativeOpenCount>::mutate(pair, |n| *n += 1);
+		<BuyOrderNativeCount>::insert(pair, index + 1);
+		Self::add_to_book(Market::Native(pair), OrderSide::Buy, ratio, index);
+		index
+	}
+
+	/// Rest a sell order on the book. Its escrow and deposit must already be held.
+	fn insert_sell_order(
+		pair: PairIndex,
+		seller: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+		deposit: BalanceOf<T>) -> SellOrderIndex {
+
+		let index = <SellOrderCount>::get(pair);
+		let created = <system::Module<T>>::block_number();
+
+		<SellOrder<T>>::insert((pair, index), SellOrderInfo {
+			order_id: index,
+			pair,
+			seller: seller.clone(),
+			volume,
+			ratio,
+			deposit,
+			created
+		});
+
+		<SellOrderList>::insert(pair, index, ());
+		<SellOrderUserList<T>>::insert((pair, seller), index, ());
+
+		<SellOrderOpenCount>::mutate(pair, |n| *n += 1);
+		<SellOrderCount>::insert(pair, index + 1);
+		Self::add_to_book(Market::Pair(pair), OrderSide::Sell, ratio, index);
+		index
+	}
+
+	/// Rest a native sell order on the book. Its escrow and deposit must already be held.
+	fn insert_native_sell_order(
+		pair: PairNativeIndex,
+		seller: AccountIdOf<T>,
+		volume: BalanceOf<T>,
+		ratio: BalanceOf<T>,
+		deposit: BalanceOf<T>) -> SellOrderNativeIndex {
+
+		let index = <SellOrderNativeCount>::get(pair);
+		let created = <system::Module<T>>::block_number();
+
+		<SellOrderNative<T>>::insert((pair, index), SellOrderNativeInfo {
+			order_id: index,
+			pair,
+			seller: seller.clone(),
+			volume,
+			ratio,
+			deposit,
+			created
+		});
+
+		<SellOrderNativeList>::insert(pair, index, ());
+		<SellOrderNativeUserList<T>>::insert((pair, seller), index, ());
+
+		<SellOrderNativeOpenCount>::mutate(pair, |n| *n += 1);
+		<SellOrderNativeCount>::insert(pair, index + 1);
+		Self::add_to_book(Market::Native(pair), OrderSide::Sell, ratio, index);
+		index
+	}
+
+	/// Take a buy order off the book, refunding what is left of its escrow.
+	fn close_buy_order(pair: PairIndex, order: BuyOrderInfoOf<T>) -> DispatchResult {
+		if !order.volume.is_zero() {
+			let base = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.base;
+			<Token::Module<T>>::transfer_(base, Self::account_operation(), order.buyer.clone(), order.volume)?;
+		}
+		<BuyOrder<T>>::remove((pair, order.order_id));
+		<BuyOrderList>::remove(pair, order.order_id);
+		<BuyOrderUserList<T>>::remove((pair, order.buyer.clone()), order.order_id);
+		<BuyOrderOpenCount>::mutate(pair, |n| *n = n.saturating_sub(1));
+		Self::remove_from_book(Market::Pair(pair), OrderSide::Buy, order.order_id);
+		Self::release_order_slot(&order.buyer, order.deposit);
+		Ok(())
+	}
+
+	/// Take a native buy order off the book, refunding what is left of its escrow.
+	fn close_native_buy_order(pair: PairNativeIndex, order: BuyOrderNativeInfoOf<T>) -> DispatchResult {
+		if !order.volume.is_zero() {
+			Self::transfer_coin(Self::account_operation(), order.buyer.clone(), order.volume)?;
+		}
+		<BuyOrderNative<T>>::remove((pair, order.order_id));
+		<BuyOrderNativeList>::remove(pair, order.order_id);
+		<BuyOrderNativeUserList<T>>::remove((pair, order.buyer.clone()), order.order_id);
+		<BuyOrderNativeOpenCount>::mutate(pair, |n| *n = n.saturating_sub(1));
+		Self::remove_from_book(Market::Native(pair), OrderSide::Buy, order.order_id);
+		Self::release_order_slot(&order.buyer, order.deposit);
+		Ok(())
+	}
+
+	/// Take a sell order off the book, refunding what is left of its escrow.
+	fn close_sell_order(pair: PairIndex, order: SellOrderInfoOf<T>) -> DispatchResult {
+		if !order.volume.is_zero() {
+			let target = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.target;
+			<Token::Module<T>>::transfer_(target, Self::account_operation(), order.seller.clone(), order.volume)?;
+		}
+		<SellOrder<T>>::remove((pair, order.order_id));
+		<SellOrderList>::remove(pair, order.order_id);
+		<SellOrderUserList<T>>::remove((pair, order.seller.clone()), order.order_id);
+		<SellOrderOpenCount>::mutate(pair, |n| *n = n.saturating_sub(1));
+		Self::remove_from_book(Market::Pair(pair), OrderSide::Sell, order.order_id);
+		Self::release_order_slot(&order.seller, order.deposit);
+		Ok(())
+	}
+
+	/// Take a native sell order off the book, refunding what is left of its escrow.
+	fn close_native_sell_order(pair: PairNativeIndex, order: SellOrderNativeInfoOf<T>) -> DispatchResult {
+		if !order.volume.is_zero() {
+			let target = <PairNative<T>>::get(pair).target;
+			<Token::Module<T>>::transfer_(target, Self::account_operation(), order.seller.clone(), order.volume)?;
+		}
+		<SellOrderNative<T>>::remove((pair, order.order_id));
+		<SellOrderNativeList>::remove(pair, order.order_id);
+		<SellOrderNativeUserList<T>>::remove((pair, order.seller.clone()), order.order_id);
+		<SellOrderNativeOpenCount>::mutate(pair, |n| *n = n.saturating_sub(1));
+		Self::remove_from_book(Market::Native(pair), OrderSide::Sell, order.order_id);
+		Self::release_order_slot(&order.seller, order.deposit);
+		Ok(())
+	}
+
+
+	/// Size a fill between a buy order holding `buy_volume` of `base` and a sell order
+	/// holding `sell_volume` of `target`, executed at `ratio`.
+	///
+	/// Returns `(base_volume, target_volume)`. `base_volume` never exceeds `buy_volume`
+	/// and `target_volume` never exceeds `sell_volume`, so both escrows always cover it.
+	fn fill(
+		buy_volume: BalanceOf<T>,
+		sell_volume: BalanceOf<T>,
+		ratio: BalanceOf<T>) -> (BalanceOf<T>, BalanceOf<T>) {
+
+		let affordable = Self::target_for_base(buy_volume, ratio);
+		let target_volume = if sell_volume < affordable { sell_volume } else { affordable };
+		let base_volume = Self::base_for_target(target_volume, ratio);
+
+		(base_volume, target_volume)
+	}
+
+	/// Amount of `base` that buys `target_volume` at `ratio`, rounded down.
+	pub fn base_for_target(target_volume: BalanceOf<T>, ratio: BalanceOf<T>) -> BalanceOf<T> {
+		multiply_by_rational(target_volume.saturated_into(), ratio.saturated_into(), PRICE_SCALE)
+			.unwrap_or(u128::max_value())
+			.saturated_into()
+	}
+
+	/// Amount of `target` that `base_volume` buys at `ratio`, rounded down.
+	pub fn target_for_base(base_volume: BalanceOf<T>, ratio: BalanceOf<T>) -> BalanceOf<T> {
+		if ratio.is_zero() {
+			return Zero::zero();
+		}
+		multiply_by_rational(base_volume.saturated_into(), PRICE_SCALE, ratio.saturated_into())
+			.unwrap_or(u128::max_value())
+			.saturated_into()
+	}
+
+	/// Settle a fill: `base_volume` leaves the buyer's escrow for the seller and
+	/// `target_volume` leaves the seller's escrow for the buyer, each less the vault fee.
+	#[transactional]
 	fn swap(pair: u128, 
 		seller: AccountIdOf<T>, 
 		buyer: AccountIdOf<T>,
-		seller_volume: BalanceOf<T>, 
-		buyer_volume: BalanceOf<T>, 
-		ratio:BalanceOf<T>) -> () {
+		base_volume: BalanceOf<T>, 
+		target_volume: BalanceOf<T>, 
+		ratio:BalanceOf<T>) -> DispatchResult {
 		
 		let exchange = Self::account_operation();
 		let vault = Self::account_vault();
-		let base = <Pair<T>>::get(pair).unwrap().base;
-		let target = <Pair<T>>::get(pair).unwrap().target;
-		let p999 = sp_runtime::Permill::from_parts(999000);
+		let base = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.base;
+		let target = <Pair<T>>::get(pair).ok_or(Error::<T>::PairNotFound)?.target;
+		let after_fee = Self::trade_fee().left_from_one();
 
-		let base_after_fee = p999 * seller_volume;		
-		<Token::Module<T>>::transfer_(base, exchange.clone(), seller.clone(), base_after_fee);
-		<Token::Module<T>>::transfer_(base, exchange.clone(), vault.clone(), seller_volume - base_after_fee);
-		let target_after_fee = p999 * buyer_volume;
-		<Token::Module<T>>::transfer_(target, exchange.clone(), buyer.clone(), target_after_fee);
-		<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), buyer_volume - target_after_fee);
+		let base_after_fee = after_fee * base_volume;		
+		<Token::Module<T>>::transfer_(base, exchange.clone(), seller.clone(), base_after_fee)?;
+		<Token::Module<T>>::transfer_(base, exchange.clone(), vault.clone(), base_volume - base_after_fee)?;
+		let target_after_fee = after_fee * target_volume;
+		<Token::Module<T>>::transfer_(target, exchange.clone(), buyer.clone(), target_after_fee)?;
+		<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), target_volume - target_after_fee)?;
 
 		let created = <system::Module<T>>::block_number();	
 		let index = <TradeCount>::get(pair);	
@@ -860,227 +1843,150 @@ impl<T: Trait> Module<T> {
 			pair,
 			seller,
 			buyer,
-			volume: buyer_volume,
+			volume: target_volume,
 			ratio,
 			created,
 		});		
 		<TradeCount>::mutate(pair, |v| *v += 1);		
-		Self::deposit_event(RawEvent::TradeCreated(index, pair, ratio, buyer_volume));			
+		Self::record_trade(Market::Pair(pair), ratio, target_volume);
+		Self::deposit_event(RawEvent::TradeCreated(index, pair, ratio, target_volume));			
+		Ok(())
 	}
 
-	fn match_orders() -> () {
-
-		let all_pairs = <PairCount>::get();
-		let min_volume = <MinimumVolume<T>>::get();
-		let pre_: BalanceOf<T> = 1000000.into();
-		let rationalisation: BalanceOf<T> = pre_* pre_;	
-		
-		for pair in 0..all_pairs {
-
-			let _buy_orders = <BuyOrderList>::get(pair);
-			let _sell_orders = <SellOrderList>::get(pair);
-
-			let buy_orders_iter = _buy_orders.iter();				
-
-			for buy_item_number in buy_orders_iter {
-				let buy_item = <BuyOrder<T>>::get((pair, buy_item_number));
-
-				let sell_orders_iter = _sell_orders.iter();
-
-				for sell_item_number in sell_orders_iter {
-
-					let sell_item = <SellOrder<T>>::get((pair, sell_item_number));
-
-					let buy_account = &buy_item.buyer;
-					let buy_order_id = buy_item.order_id;
-					let buy_volume = buy_item.volume;
-					let buy_price = buy_item.ratio;	
-					let buy_created = &buy_item.created;		
-
-					let sell_account = &sell_item.seller;
-					let sell_order_id = sell_item.order_id;
-					let sell_volume = sell_item.volume;
-					let sell_price = sell_item.ratio;
-					let sell_created = &sell_item.created;	
-
-					let trade_price = buy_price;
-
-					if buy_price >= sell_price && buy_volume >= min_volume && sell_volume >= min_volume {
-						
-						let buy_implied_volume = buy_volume * buy_price / rationalisation;
-						let sell_implied_volume = sell_price / sell_volume * rationalisation;
-						let base_volume;
-						let target_volume;						
-
-						if sell_volume < buy_implied_volume {
-							base_volume = sell_implied_volume;
-							target_volume = sell_volume;
-						} else {
-							base_volume = buy_implied_volume;
-							target_volume = buy_volume;
-						}
-
-						let new_buyer_volume = buy_volume - base_volume;
-						let new_seller_volume =  sell_volume - target_volume;
-
-						BuyOrder::<T>::mutate((pair, buy_order_id), |v| *v = BuyOrderInfo {
-							order_id: buy_order_id,
-							pair: pair,
-							buyer: buy_account.clone(),
-							volume: new_buyer_volume,
-							ratio: buy_price,
-							created: *buy_created
-						});
-						
-						SellOrder::<T>::mutate((pair, sell_order_id), |v| *v = SellOrderInfo {
-							order_id: sell_order_id,
-							pair: pair,
-							seller: sell_account.clone(),
-							volume: new_seller_volume,
-							ratio: sell_price,
-							created: *sell_created
-						});									
-
-						// SWAP
-						Self::swap(pair.clone(), 
-							sell_account.clone(), 
-							buy_account.clone(), 
-							base_volume.clone(), 
-							target_volume.clone(), 
-							trade_price);	
-						// Reduce volume
-					} else {
-						
-					}
-					
-
-					// if buy_price >= sell_price {
-
-					// 	let matched_price = buy_price;
-					// 	let pair: u128 = pair.into();
-					// 	let mut seller_volume = sell_volume;
-					// 	let mut buyer_volume =  buy_volume;
-					// 	let mut matched_volume = sell_volume;
-
-					// 	if buy_volume >= sell_volume {
-					// 		matched_volume = sell_volume
-					// 		seller_volume -= sell_volume;
-					// 		buyer_volume = buy_volume - sell_volume;
-
-					// 	} else if buy_volume < sell_volume && buy_volume > min_volume {
-					// 		matched_volume = buy_volume;
-					// 		seller_volume -= buy_volume;
-					// 		buyer_volume = sell_volume - buy_volume;	
-
-					// 	} 
-					
-																					
-
-					// } 						
-				}	
+	/// Weight of `MaxMatchingSteps` matching steps, with reading and writing the queues.
+	fn matching_weight() -> Weight {
+		let steps = T::MaxMatchingSteps::get() as Weight;
+		T::DbWeight::get().reads_writes(
+			2 + MATCH_STEP_READS.saturating_mul(steps),
+			2 + MATCH_STEP_WRITES.saturating_mul(steps),
+		)
+	}
 
+	/// Match the `MarketsToMatch` in turn, taking one of `steps` per market visited. A
+	/// market still crossed when `steps` run out, or holding a fill that could not be
+	/// paid, goes to the back of the queue.
+	fn match_markets(steps: &mut u32) -> () {
+		let mut queue = <MarketsToMatch>::get();
+		let mut again = Vec::new();
+
+		while *steps > 0 && !queue.is_empty() {
+			*steps -= 1;
+			let market = queue.remove(0);
+			let settled = match market {
+				Market::Pair(pair) => Self::match_pair(pair, steps),
+				Market::Native(pair) => Self::match_native_pair(pair, steps),
+			};
+			if !settled {
+				again.push(market);
 			}
-		
+		}
 
-			let buy_orders_iter2 = _buy_orders.iter();	
-			for buy_item_number in buy_orders_iter2 {
-				let buy_item = <BuyOrder<T>>::get((pair, buy_item_number));
-				let buy_order_id = buy_item.order_id;
-				let buy_volume = buy_item.volume;		
-				let buyer = buy_item.buyer;			
+		queue.extend(again);
+		<MarketsToMatch>::put(queue);
+	}
 
-				if buy_volume <= min_volume {					
-					let mut buy_order_list = <BuyOrderList>::get(pair);
+	/// Whether a buy order of `volume` base at `ratio` is too small to stay on the book:
+	/// below the minimum notional, or unable to buy a single unit of target.
+	fn is_buy_dust(limits: &PairLimitsOf<T>, volume: BalanceOf<T>, ratio: BalanceOf<T>) -> bool {
+		volume.is_zero() || volume < limits.min_notional || Self::target_for_base(volume, ratio).is_zero()
+	}
 
-					match buy_order_list.binary_search(&buy_item_number) {
+	/// Whether a sell order of `volume` target is too small to stay on the book.
+	fn is_sell_dust(limits: &PairLimitsOf<T>, volume: BalanceOf<T>) -> bool {
+		volume.is_zero() || volume < limits.min_amount
+	}
 
-						Ok(index) => {
-							buy_order_list.remove(index);
-							<BuyOrderList>::insert(pair, buy_order_list);
+	/// Fill crossing orders of `pair` from the best prices inwards at the buy order's
+	/// ratio, taking one of `steps` per pair of orders tried, and close what is left too
+	/// small to trade. Returns whether the book was left uncrossed.
+	fn match_pair(pair: PairIndex, steps: &mut u32) -> bool {
+		let market = Market::Pair(pair);
+		let limits = Self::pair_limits(market);
+		let sells = Self::order_book(market, OrderSide::Sell);
+		// Sell orders closed, or left to their frozen owner to close, in this pass.
+		let mut closed: Vec<SellOrderIndex> = Vec::new();
+		let mut settled = true;
+
+		for (buy_ratio, buy_id) in Self::order_book(market, OrderSide::Buy) {
+			match sells.iter().find(|(_, id)| !closed.contains(id)) {
+				Some((sell_ratio, _)) if *sell_ratio <= buy_ratio => {},
+				_ => break,
+			}
 
-							<BuyOrder<T>>::remove((pair, buy_order_id));
-						},
-						Err(_) => {
-							
-						},
+			for (sell_ratio, sell_id) in sells.iter() {
+				if *sell_ratio > buy_ratio {
+					break;
+				}
+				if closed.contains(sell_id) {
+					continue;
+				}
+				if *steps == 0 {
+					return false;
+				}
+				*steps -= 1;
+
+				let mut buy_item = <BuyOrder<T>>::get((pair, buy_id));
+				let mut sell_item = <SellOrder<T>>::get((pair, sell_id));
+
+				if !Self::is_buy_dust(&limits, buy_item.volume, buy_ratio) && !Self::is_sell_dust(&limits, sell_item.volume) {
+					let (base_volume, target_volume) = Self::fill(buy_item.volume, sell_item.volume, buy_ratio);
+					if base_volume.is_zero() {
+						continue;
 					}
 
-					let mut buy_order_user_list = <BuyOrderUserList<T>>::get((pair, buyer.clone()));
-
-					match buy_order_user_list.binary_search(&buy_item_number) {
-
-						Ok(index) => {
-							buy_order_user_list.remove(index);
-							<BuyOrderUserList<T>>::insert((pair, buyer.clone()), buy_order_user_list);
-						},
-						Err(_) => {
-							
-						},
-					}					
-				}		
-			}				
-
-			let sell_orders_iter2 = _sell_orders.iter();
-
-			for sell_item_number in sell_orders_iter2 {
-				let sell_item = <SellOrder<T>>::get((pair, sell_item_number));	
-				let sell_order_id = sell_item.order_id;
-				let sell_volume = sell_item.volume;	
-				let seller = sell_item.seller;
-				
-				if sell_volume <= min_volume {					
-					let mut sell_order_list = <SellOrderList>::get(pair);
-					match sell_order_list.binary_search(&sell_item_number) {
-
-						Ok(index) => {
-							sell_order_list.remove(index);
-							<SellOrderList>::insert(pair, sell_order_list);							
-							<SellOrder<T>>::remove((pair, sell_order_id));
-						},
-						Err(_) => {
-							
-						},
+					// A side frozen since placing its order cannot be paid, so it does not trade.
+					if Self::swap(pair, 
+						sell_item.seller.clone(), 
+						buy_item.buyer.clone(), 
+						base_volume, 
+						target_volume, 
+						buy_ratio).is_err() {
+						settled = false;
+						continue;
 					}
 
-					let mut sell_order_user_list = <SellOrderUserList<T>>::get((pair, seller.clone()));
-
-					match sell_order_user_list.binary_search(&sell_item_number) {
-
-						Ok(index) => {
-							sell_order_user_list.remove(index);
-							<SellOrderUserList<T>>::insert((pair,seller.clone()), sell_order_user_list);
-						},
-						Err(_) => {
-							
-						},
-					}						
-				}					
+					buy_item.volume = buy_item.volume - base_volume;
+					sell_item.volume = sell_item.volume - target_volume;
+					<BuyOrder<T>>::mutate((pair, buy_id), |v| v.volume = buy_item.volume);
+					<SellOrder<T>>::mutate((pair, sell_id), |v| v.volume = sell_item.volume);
+				}
+
+				// A frozen owner keeps the dust order until thawed.
+				if Self::is_sell_dust(&limits, sell_item.volume) {
+					let _ = Self::close_sell_order(pair, sell_item);
+					closed.push(*sell_id);
+				}
+				if Self::is_buy_dust(&limits, buy_item.volume, buy_ratio) {
+					let _ = Self::close_buy_order(pair, buy_item);
+					break;
+				}
+			}
+		}
 
-				//SellOrderCount::mutate(pair, |v| *v -= 1);	
-			}				
-					
-	
-		}		
+		settled
 	}
 
+	/// Settle a native fill: `base_volume` of the native currency leaves the buyer's
+	/// escrow for the seller and `target_volume` leaves the seller's escrow for the buyer.
+	#[transactional]
 	fn swap_native(pair: u128, 
 		seller: AccountIdOf<T>, 
 		buyer: AccountIdOf<T>,
-		seller_volume: BalanceOf<T>, 
-		buyer_volume: BalanceOf<T>, 
-		ratio:BalanceOf<T>) -> () {
+		base_volume: BalanceOf<T>, 
+		target_volume: BalanceOf<T>, 
+		ratio:BalanceOf<T>) -> DispatchResult {
 		
 		let exchange = Self::account_operation();
 		let vault = Self::account_vault();
 		let target = <PairNative<T>>::get(pair).target;
-		let p999 = sp_runtime::Permill::from_parts(999000);
+		let after_fee = Self::trade_fee().left_from_one();
 
-		let base_after_fee = p999 * seller_volume;		
-		Self::transfer_coin(exchange.clone(), seller.clone(), base_after_fee);
-		Self::transfer_coin(exchange.clone(), vault.clone(), seller_volume - base_after_fee);
-		let target_after_fee = p999 * buyer_volume;
-		<Token::Module<T>>::transfer_(target, exchange.clone(), buyer.clone(), target_after_fee);
-		<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), buyer_volume - target_after_fee);
+		let base_after_fee = after_fee * base_volume;		
+		Self::transfer_coin(exchange.clone(), seller.clone(), base_after_fee)?;
+		Self::transfer_coin(exchange.clone(), vault.clone(), base_volume - base_after_fee)?;
+		let target_after_fee = after_fee * target_volume;
+		<Token::Module<T>>::transfer_(target, exchange.clone(), buyer.clone(), target_after_fee)?;
+		<Token::Module<T>>::transfer_(target, exchange.clone(), vault.clone(), target_volume - target_after_fee)?;
 
 		let created = <system::Module<T>>::block_number();	
 		let index = <TradeNativeCount>::get(pair);	
@@ -1089,181 +1995,88 @@ impl<T: Trait> Module<T> {
 			pair,
 			seller,
 			buyer,
-			volume: buyer_volume,
+			volume: target_volume,
 			ratio,
 			created,
 		});	
 		<TradeNativeCount>::mutate(pair, |v| *v += 1);		
-		Self::deposit_event(RawEvent::TradeNativeCreated(index, pair, ratio, buyer_volume));			
+		Self::record_trade(Market::Native(pair), ratio, target_volume);
+		Self::deposit_event(RawEvent::TradeNativeCreated(index, pair, ratio, target_volume));			
+		Ok(())
 	}
 
-	fn match_native_orders() -> () {
-
-		let all_pairs = <PairNativeCount>::get();
-		let min_volume = <MinimumVolume<T>>::get();
-		let pre_: BalanceOf<T> = 1000000.into();
-		let rationalisation: BalanceOf<T> = pre_* pre_;	
-		
-		for pair in 0..all_pairs {
-
-			let _buy_orders = <BuyOrderNativeList>::get(pair);
-			let _sell_orders = <SellOrderNativeList>::get(pair);
-
-			let buy_orders_iter = _buy_orders.iter();				
-
-			for buy_item_number in buy_orders_iter {
-				let buy_item = <BuyOrderNative<T>>::get((pair, buy_item_number));
-
-				let sell_orders_iter = _sell_orders.iter();
-
-				for sell_item_number in sell_orders_iter {
-					let sell_item = <SellOrderNative<T>>::get((pair, sell_item_number));
-
-					let buy_account = &buy_item.buyer;
-					let buy_order_id = buy_item.order_id;
-					let buy_volume = buy_item.volume;
-					let buy_price = buy_item.ratio;	
-					let buy_created = &buy_item.created;		
-
-					let sell_account = &sell_item.seller;
-					let sell_order_id = sell_item.order_id;
-					let sell_volume = sell_item.volume;
-					let sell_price = sell_item.ratio;
-					let sell_created = &sell_item.created;	
-
-					let trade_price = buy_price;
-
-					if buy_price >= sell_price && buy_volume >= min_volume && sell_volume >= min_volume {
-						
-						let buy_implied_volume = buy_volume * buy_price / rationalisation;
-						let sell_implied_volume = sell_price / sell_volume * rationalisation;
-						let base_volume;
-						let target_volume;						
-
-						if sell_volume < buy_implied_volume {
-							base_volume = sell_implied_volume;
-							target_volume = sell_volume;
-						} else {
-							base_volume = buy_implied_volume;
-							target_volume = buy_volume;
-						}
-
-						let new_buyer_volume = buy_volume - base_volume;
-						let new_seller_volume =  sell_volume - target_volume;
-
-						BuyOrderNative::<T>::mutate((pair, buy_order_id), |v| *v = BuyOrderNativeInfo {
-							order_id: buy_order_id,
-							pair: pair,
-							buyer: buy_account.clone(),
-							volume: new_buyer_volume,
-							ratio: buy_price,
-							created: *buy_created
-						});
-						
-						SellOrderNative::<T>::mutate((pair, sell_order_id), |v| *v = SellOrderNativeInfo {
-							order_id: sell_order_id,
-							pair: pair,
-							seller: sell_account.clone(),
-							volume: new_seller_volume,
-							ratio: sell_price,
-							created: *sell_created
-						});									
-
-						// SWAP
-						Self::swap_native(pair.clone(), 
-							sell_account.clone(), 
-							buy_account.clone(), 
-							base_volume.clone(), 
-							target_volume.clone(), 
-							trade_price);	
-						// Reduce volume
-					} else {
-						
-					}
-					
-
-					
-				}	
-
+	/// Native counterpart of `match_pair`.
+	fn match_native_pair(pair: PairNativeIndex, steps: &mut u32) -> bool {
+		let market = Market::Native(pair);
+		let limits = Self::pair_limits(market);
+		let sells = Self::order_book(market, OrderSide::Sell);
+		// Sell orders closed, or left to their frozen owner to close, in this pass.
+		let mut closed: Vec<SellOrderNativeIndex> = Vec::new();
+		let mut settled = true;
+
+		for (buy_ratio, buy_id) in Self::order_book(market, OrderSide::Buy) {
+			match sells.iter().find(|(_, id)| !closed.contains(id)) {
+				Some((sell_ratio, _)) if *sell_ratio <= buy_ratio => {},
+				_ => break,
 			}
 
-
-			let buy_orders_iter2 = _buy_orders.iter();	
-			for buy_item_number in buy_orders_iter2 {
-				let buy_item = <BuyOrderNative<T>>::get((pair, buy_item_number));
-				let buy_order_id = buy_item.order_id;
-				let buy_volume = buy_item.volume;	
-				let buyer = buy_item.buyer;				
-
-				if buy_volume <= min_volume {					
-					let mut buy_order_list = <BuyOrderNativeList>::get(pair);
-					match buy_order_list.binary_search(&buy_item_number) {
-
-						Ok(index) => {
-							buy_order_list.remove(index);
-							<BuyOrderNativeList>::insert(pair, buy_order_list);							
-							<BuyOrderNative<T>>::remove((pair, buy_order_id));
-						},
-						Err(_) => {
-							
-						},
+			for (sell_ratio, sell_id) in sells.iter() {
+				if *sell_ratio > buy_ratio {
+					break;
+				}
+				if closed.contains(sell_id) {
+					continue;
+				}
+				if *steps == 0 {
+					return false;
+				}
+				*steps -= 1;
+
+				let mut buy_item = <BuyOrderNative<T>>::get((pair, buy_id));
+				let mut sell_item = <SellOrderNative<T>>::get((pair, sell_id));
+
+				if !Self::is_buy_dust(&limits, buy_item.volume, buy_ratio) && !Self::is_sell_dust(&limits, sell_item.volume) {
+					let (base_volume, target_volume) = Self::fill(buy_item.volume, sell_item.volume, buy_ratio);
+					if base_volume.is_zero() {
+						continue;
 					}
 
-					let mut buy_order_user_list = <BuyOrderNativeUserList<T>>::get((pair, buyer.clone()));
-
-					match buy_order_user_list.binary_search(&buy_item_number) {
-
-						Ok(index) => {
-							buy_order_user_list.remove(index);
-							<BuyOrderNativeUserList<T>>::insert((pair, buyer.clone()), buy_order_user_list);
-						},
-						Err(_) => {
-							
-						},
-					}						
-				}	
-			}				
-
-			let sell_orders_iter2 = _sell_orders.iter();
-
-			for sell_item_number in sell_orders_iter2 {
-				let sell_item = <SellOrderNative<T>>::get((pair, sell_item_number));
-				let sell_order_id = sell_item.order_id;
-				let sell_volume = sell_item.volume;		
-				let seller = sell_item.seller;			
-
-				if sell_volume <= min_volume {					
-					let mut sell_order_list = <SellOrderNativeList>::get(pair);
-					match sell_order_list.binary_search(&sell_item_number) {
-
-						Ok(index) => {
-							sell_order_list.remove(index);
-							<SellOrderNativeList>::insert(pair, sell_order_list);							
-							<SellOrderNative<T>>::remove((pair, sell_order_id));
-						},
-						Err(_) => {
-							
-						},
+					// A side frozen since placing its order cannot be paid, so it does not trade.
+					if Self::swap_native(pair, 
+						sell_item.seller.clone(), 
+						buy_item.buyer.clone(), 
+						base_volume, 
+						target_volume, 
+						buy_ratio).is_err() {
+						settled = false;
+						continue;
 					}
 
-					let mut sell_order_user_list = <SellOrderNativeUserList<T>>::get((pair, seller.clone()));
-
-					match sell_order_user_list.binary_search(&sell_item_number) {
-
-						Ok(index) => {
-							sell_order_user_list.remove(index);
-							<SellOrderNativeUserList<T>>::insert((pair,seller.clone()), sell_order_user_list);
-						},
-						Err(_) => {
-							
-						},
-					}						
-				}	
-			}				
-					
-	
-		}		
+					buy_item.volume = buy_item.volume - base_volume;
+					sell_item.volume = sell_item.volume - target_volume;
+					<BuyOrderNative<T>>::mutate((pair, buy_id), |v| v.volume = buy_item.volume);
+					<SellOrderNative<T>>::mutate((pair, sell_id), |v| v.volume = sell_item.volume);
+				}
+
+				// A frozen owner keeps the dust order until thawed.
+				if Self::is_sell_dust(&limits, sell_item.volume) {
+					let _ = Self::close_native_sell_order(pair, sell_item);
+					closed.push(*sell_id);
+				}
+				if Self::is_buy_dust(&limits, buy_item.volume, buy_ratio) {
+					let _ = Self::close_native_buy_order(pair, buy_item);
+					break;
+				}
+			}
+		}
+
+		settled
 	}
 	
 }
 
+impl<T: Trait> Oracle<Market, BalanceOf<T>, T::BlockNumber> for Module<T> {
+	fn twap(market: Market, window: T::BlockNumber) -> Option<BalanceOf<T>> {
+		Self::twap(market, window)
+	}
+}
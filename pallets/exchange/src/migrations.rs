@@ -0,0 +1,396 @@
+//! Storage migrations of the exchange pallet.
+//!
+//! `migrate` is run from `on_runtime_upgrade` and brings storage from whatever
+//! `StorageVersion` it is at up to the current `Releases`, one version at a time.
+
+use super::*;
+use frame_support::{
+	storage::{migration::{take_storage_value, StorageIterator}, IterableStorageMap},
+	traits::Get,
+	weights::Weight,
+};
+
+/// Run every migration the stored version is behind on.
+pub fn migrate<T: Trait>() -> Weight {
+	let mut weight = T::DbWeight::get().reads(1);
+
+	if StorageVersion::get() == Releases::V0 {
+		weight = weight.saturating_add(v1::migrate::<T>());
+		StorageVersion::put(Releases::V1);
+	}
+	if StorageVersion::get() == Releases::V1 {
+		weight = weight.saturating_add(v2::migrate::<T>());
+		StorageVersion::put(Releases::V2);
+	}
+
+	weight
+}
+
+/// V0 to V1: order deposits, indexed order books and per-pair limits.
+///
+/// V0 orders carried no deposit and their books were `Vec`s of ids per pair and per
+/// `(pair, account)` that also kept filled orders, with no price order. Their `ratio`
+/// was the amount of target per base, the inverse of today's. Minimum volume and ratio
+/// were global values.
+pub mod v1 {
+	use super::*;
+
+	/// Any V0 order. Buy and sell orders share this layout.
+	#[derive(Encode, Decode)]
+	pub struct OldOrderInfo<AccountId, Balance, BlockNumber> {
+		pub order_id: u128,
+		pub pair: u128,
+		pub owner: AccountId,
+		pub volume: Balance,
+		pub ratio: Balance,
+		pub created: BlockNumber
+	}
+
+	type OldOrderInfoOf<T> = OldOrderInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+
+	const MODULE: &[u8] = b"Exchange";
+
+	/// Invert a V0 ratio, target per base, into base per target, rounding in the order
+	/// owner's favour: down for the most a buyer pays, up for the least a seller takes.
+	/// `None` for a ratio with no non-zero inverse, whose order is refunded instead.
+	pub fn invert_ratio<T: Trait>(ratio: BalanceOf<T>, round_up: bool) -> Option<BalanceOf<T>> {
+		let scale_squared = PRICE_SCALE * PRICE_SCALE;
+		let old: u128 = ratio.saturated_into();
+		if old == 0 {
+			return None;
+		}
+		let mut new = scale_squared / old;
+		if round_up && scale_squared % old != 0 {
+			new += 1;
+		}
+		if new == 0 {
+			return None;
+		}
+		Some(new.saturated_into())
+	}
+
+	/// `(orders, book, user lists)` storage names of each kind of order.
+	const BOOKS: [(&[u8], &[u8], &[u8]); 4] = [
+		(b"BuyOrder", b"BuyOrderList", b"BuyOrderUserList"),
+		(b"BuyOrderNative", b"BuyOrderNativeList", b"BuyOrderNativeUserList"),
+		(b"SellOrder", b"SellOrderList", b"SellOrderUserList"),
+		(b"SellOrderNative", b"SellOrderNativeList", b"SellOrderNativeUserList"),
+	];
+
+	/// Remove a V0 `Vec` list and return its entries with their decoded keys.
+	fn take_lists<K: Decode>(item: &[u8]) -> Vec<(K, Vec<u128>)> {
+		StorageIterator::<Vec<u128>>::new(MODULE, item)
+			.drain()
+			// Keys are `blake2_128_concat`: a 16 byte hash followed by the key itself.
+			.filter_map(|(key, ids)| K::decode(&mut &key[16..]).ok().map(|k| (k, ids)))
+			.collect()
+	}
+
+	pub fn migrate<T: Trait>() -> Weight {
+		let mut reads: Weight = 0;
+		let mut writes: Weight = 0;
+		let translated = sp_std::cell::Cell::new(0 as Weight);
+		// Orders whose ratio cannot be inverted, as `(market, side, owner, volume)`.
+		let refunds = sp_std::cell::RefCell::new(Vec::new());
+
+		// Orders gain a deposit. None was reserved for them, so it is zero.
+		<BuyOrder<T>>::translate::<OldOrderInfoOf<T>, _>(|_, old| {
+			translated.set(translated.get() + 1);
+			let ratio = match invert_ratio::<T>(old.ratio, false) {
+				Some(ratio) => ratio,
+				None => {
+					refunds.borrow_mut().push((Market::Pair(old.pair), OrderSide::Buy, old.owner, old.volume));
+					return None;
+				},
+			};
+			Some(BuyOrderInfo {
+				order_id: old.order_id,
+				pair: old.pair,
+				buyer: old.owner,
+				volume: old.volume,
+				ratio,
+				deposit: Zero::zero(),
+				created: old.created
+			})
+		});
+		<BuyOrderNative<T>>::translate::<OldOrderInfoOf<T>, _>(|_, old| {
+			translated.set(translated.get() + 1);
+			let ratio = match invert_ratio::<T>(old.ratio, false) {
+				Some(ratio) => ratio,
+				None => {
+					refunds.borrow_mut().push((Market::Native(old.pair), OrderSide::Buy, old.owner, old.volume));
+					return None;
+				},
+			};
+			Some(BuyOrderNativeInfo {
+				order_id: old.order_id,
+				pair: old.pair,
+				buyer: old.owner,
+				volume: old.volume,
+				ratio,
+				deposit: Zero::zero(),
+				created: old.created
+			})
+		});
+		<SellOrder<T>>::translate::<OldOrderInfoOf<T>, _>(|_, old| {
+			translated.set(translated.get() + 1);
+			let ratio = match invert_ratio::<T>(old.ratio, true) {
+				Some(ratio) => ratio,
+				None => {
+					refunds.borrow_mut().push((Market::Pair(old.pair), OrderSide::Sell, old.owner, old.volume));
+					return None;
+				},
+			};
+			Some(SellOrderInfo {
+				order_id: old.order_id,
+				pair: old.pair,
+				seller: old.owner,
+				volume: old.volume,
+				ratio,
+				deposit: Zero::zero(),
+				created: old.created
+			})
+		});
+		<SellOrderNative<T>>::translate::<OldOrderInfoOf<T>, _>(|_, old| {
+			translated.set(translated.get() + 1);
+			let ratio = match invert_ratio::<T>(old.ratio, true) {
+				Some(ratio) => ratio,
+				None => {
+					refunds.borrow_mut().push((Market::Native(old.pair), OrderSide::Sell, old.owner, old.volume));
+					return None;
+				},
+			};
+			Some(SellOrderNativeInfo {
+				order_id: old.order_id,
+				pair: old.pair,
+				seller: old.owner,
+				volume: old.volume,
+				ratio,
+				deposit: Zero::zero(),
+				created: old.created
+			})
+		});
+
+		reads += translated.get();
+		writes += translated.get();
+
+		// Return the escrow of the orders dropped above. Being gone from the order maps,
+		// they are left out of the books below like filled orders.
+		for (market, side, owner, volume) in refunds.into_inner() {
+			if volume.is_zero() {
+				continue;
+			}
+			if let Ok(asset) = Module::<T>::escrow_asset(market, side) {
+				let _ = Module::<T>::move_asset(asset, Module::<T>::account_operation(), owner, volume);
+			}
+			reads += 8;
+			writes += 2;
+		}
+
+		// The user lists are rebuilt from the books below.
+		for (_, _, user_list) in BOOKS.iter() {
+			writes += take_lists::<(PairIndex, AccountIdOf<T>)>(user_list).len() as Weight;
+		}
+
+		// Index open orders and drop filled ones.
+		for (pair, ids) in take_lists::<PairIndex>(BOOKS[0].1) {
+			for id in ids {
+				reads += 1; writes += 1;
+				let order = <BuyOrder<T>>::get((pair, id));
+				if order.volume.is_zero() {
+					<BuyOrder<T>>::remove((pair, id));
+					continue;
+				}
+				<BuyOrderList>::insert(pair, id, ());
+				<BuyOrderUserList<T>>::insert((pair, order.buyer.clone()), id, ());
+				<BuyOrderOpenCount>::mutate(pair, |n| *n += 1);
+				Module::<T>::add_to_book(Market::Pair(pair), OrderSide::Buy, order.ratio, id);
+				<OpenOrders<T>>::mutate(order.buyer, |n| *n += 1);
+				writes += 5;
+			}
+		}
+		for (pair, ids) in take_lists::<PairNativeIndex>(BOOKS[1].1) {
+			for id in ids {
+				reads += 1; writes += 1;
+				let order = <BuyOrderNative<T>>::get((pair, id));
+				if order.volume.is_zero() {
+					<BuyOrderNative<T>>::remove((pair, id));
+					continue;
+				}
+				<BuyOrderNativeList>::insert(pair, id, ());
+				<BuyOrderNativeUserList<T>>::insert((pair, order.buyer.clone()), id, ());
+				<BuyOrderNativeOpenCount>::mutate(pair, |n| *n += 1);
+				Module::<T>::add_to_book(Market::Native(pair), OrderSide::Buy, order.ratio, id);
+				<OpenOrders<T>>::mutate(order.buyer, |n| *n += 1);
+				writes += 5;
+			}
+		}
+		for (pair, ids) in take_lists::<PairIndex>(BOOKS[2].1) {
+			for id in ids {
+				reads += 1; writes += 1;
+				let order = <SellOrder<T>>::get((pair, id));
+				if order.volume.is_zero() {
+					<SellOrder<T>>::remove((pair, id));
+					continue;
+				}
+				<SellOrderList>::insert(pair, id, ());
+				<SellOrderUserList<T>>::insert((pair, order.seller.clone()), id, ());
+				<SellOrderOpenCount>::mutate(pair, |n| *n += 1);
+				Module::<T>::add_to_book(Market::Pair(pair), OrderSide::Sell, order.ratio, id);
+				<OpenOrders<T>>::mutate(order.seller, |n| *n += 1);
+				writes += 5;
+			}
+		}
+		for (pair, ids) in take_lists::<PairNativeIndex>(BOOKS[3].1) {
+			for id in ids {
+				reads += 1; writes += 1;
+				let order = <SellOrderNative<T>>::get((pair, id));
+				if order.volume.is_zero() {
+					<SellOrderNative<T>>::remove((pair, id));
+					continue;
+				}
+				<SellOrderNativeList>::insert(pair, id, ());
+				<SellOrderNativeUserList<T>>::insert((pair, order.seller.clone()), id, ());
+				<SellOrderNativeOpenCount>::mutate(pair, |n| *n += 1);
+				Module::<T>::add_to_book(Market::Native(pair), OrderSide::Sell, order.ratio, id);
+				<OpenOrders<T>>::mutate(order.seller, |n| *n += 1);
+				writes += 5;
+			}
+		}
+
+		// The global minimums become every pair's limits and the listing default.
+		let min_volume = take_storage_value::<BalanceOf<T>>(MODULE, b"MinimumVolume", &[]).unwrap_or_default();
+		let min_ratio = take_storage_value::<BalanceOf<T>>(MODULE, b"MinimumRatio", &[]).unwrap_or_default();
+		let limits = PairLimits {
+			min_amount: min_volume,
+			min_notional: min_volume,
+			min_price: min_ratio,
+			max_price: Zero::zero(),
+		};
+		for pair in 0..<PairCount>::get() {
+			<PairLimit<T>>::insert(Market::Pair(pair), limits.clone());
+		}
+		for pair in 0..<PairNativeCount>::get() {
+			<PairLimit<T>>::insert(Market::Native(pair), limits.clone());
+		}
+		<DefaultPairLimits<T>>::put(limits);
+		reads += 4;
+		writes += 3 + (<PairCount>::get() + <PairNativeCount>::get()) as Weight;
+
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	/// Number of V0 orders that stay open, to compare against `post_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn pre_migrate<T: Trait>() -> Result<u32, &'static str> {
+		use frame_support::{storage::migration::get_storage_value, Blake2_128Concat, StorageHasher};
+
+		if StorageVersion::get() != Releases::V0 {
+			return Err("exchange storage is not at V0");
+		}
+
+		let mut open = 0;
+		for (book, (order_item, list, _)) in BOOKS.iter().enumerate() {
+			// Buy orders come first in `BOOKS`.
+			let sell = book >= 2;
+			for (key, ids) in StorageIterator::<Vec<u128>>::new(MODULE, list) {
+				let pair = PairIndex::decode(&mut &key[16..]).map_err(|_| "undecodable V0 book key")?;
+				for id in ids {
+					let hash = Blake2_128Concat::hash(&(pair, id).encode());
+					let order = get_storage_value::<OldOrderInfoOf<T>>(MODULE, order_item, &hash)
+						.ok_or("listed V0 order is missing or undecodable")?;
+					if !order.volume.is_zero() && invert_ratio::<T>(order.ratio, sell).is_some() {
+						open += 1;
+					}
+				}
+			}
+		}
+		Ok(open)
+	}
+
+	/// Check the books against the `open` orders counted by `pre_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn post_migrate<T: Trait>(open: u32) -> Result<(), &'static str> {
+		use frame_support::storage::migration::get_storage_value;
+
+		if StorageVersion::get() == Releases::V0 {
+			return Err("exchange storage was not bumped to V1");
+		}
+
+		let mut books = 0;
+		for (pair, id, _) in <BuyOrderList as IterableStorageDoubleMap<_, _, _>>::iter() {
+			let order = <BuyOrder<T>>::try_get((pair, id)).map_err(|_| "buy order did not migrate")?;
+			if !<BuyOrderUserList<T>>::contains_key((pair, order.buyer), id) {
+				return Err("buy order missing from its user list");
+			}
+			books += 1;
+		}
+		for (pair, id, _) in <BuyOrderNativeList as IterableStorageDoubleMap<_, _, _>>::iter() {
+			let order = <BuyOrderNative<T>>::try_get((pair, id)).map_err(|_| "native buy order did not migrate")?;
+			if !<BuyOrderNativeUserList<T>>::contains_key((pair, order.buyer), id) {
+				return Err("native buy order missing from its user list");
+			}
+			books += 1;
+		}
+		for (pair, id, _) in <SellOrderList as IterableStorageDoubleMap<_, _, _>>::iter() {
+			let order = <SellOrder<T>>::try_get((pair, id)).map_err(|_| "sell order did not migrate")?;
+			if !<SellOrderUserList<T>>::contains_key((pair, order.seller), id) {
+				return Err("sell order missing from its user list");
+			}
+			books += 1;
+		}
+		for (pair, id, _) in <SellOrderNativeList as IterableStorageDoubleMap<_, _, _>>::iter() {
+			let order = <SellOrderNative<T>>::try_get((pair, id)).map_err(|_| "native sell order did not migrate")?;
+			if !<SellOrderNativeUserList<T>>::contains_key((pair, order.seller), id) {
+				return Err("native sell order missing from its user list");
+			}
+			books += 1;
+		}
+
+		let per_account: u32 = <OpenOrders<T>>::iter().map(|(_, n)| n).sum();
+		let sorted = <OrderBook<T>>::iter().map(|(_, _, book)| book.len() as u32).sum::<u32>();
+		if books != open || per_account != open || sorted != open {
+			return Err("open order count changed");
+		}
+		if get_storage_value::<BalanceOf<T>>(MODULE, b"MinimumVolume", &[]).is_some() ||
+			get_storage_value::<BalanceOf<T>>(MODULE, b"MinimumRatio", &[]).is_some() {
+			return Err("global minimums were not removed");
+		}
+		Ok(())
+	}
+}
+
+/// V1 to V2: trigger orders counted per market.
+///
+/// V1 kept no count of the trigger orders waiting on a market, so it is built from
+/// the orders themselves.
+pub mod v2 {
+	use super::*;
+
+	pub fn migrate<T: Trait>() -> Weight {
+		let mut reads: Weight = 0;
+		let mut writes: Weight = 0;
+
+		for (market, _, _) in <TriggerOrder<T>>::iter() {
+			<TriggerOrderOpenCount>::mutate(market, |n| *n += 1);
+			reads += 1;
+			writes += 1;
+		}
+
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	/// Check the per-market counts against the trigger orders.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn post_migrate<T: Trait>() -> Result<(), &'static str> {
+		if StorageVersion::get() != Releases::V2 {
+			return Err("exchange storage was not bumped to V2");
+		}
+
+		let counted: u32 = <TriggerOrderOpenCount as IterableStorageMap<_, _>>::iter().map(|(_, n)| n).sum();
+		if counted as usize != <TriggerOrder<T>>::iter().count() {
+			return Err("trigger order count does not match the orders");
+		}
+		Ok(())
+	}
+}
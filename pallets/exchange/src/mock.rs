@@ -0,0 +1,149 @@
+use crate::{Module, Trait};
+use sp_core::H256;
+use frame_support::{impl_outer_origin, parameter_types, weights::Weight};
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::Header, Perbill,
+};
+use frame_system as system;
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+	pub const ExistentialDeposit: u128 = 1;
+	pub const MaxOpenOrdersPerUser: u32 = 3;
+	pub const MaxOrdersPerSide: u32 = 4;
+	pub const MaxTriggersPerMarket: u32 = 2;
+	pub const MaxMatchingSteps: u32 = 4;
+	pub const UniqueSymbols: bool = true;
+	pub EscrowAccounts: Vec<u64> = vec![OPERATION, VAULT];
+	pub const OrderDeposit: u128 = 10;
+	pub const CandlePeriod: u64 = 10;
+	pub const VolumeWindow: u64 = 30;
+	pub const OracleGranularity: u64 = 5;
+	pub const OracleObservations: u32 = 4;
+	pub const ListingBond: u128 = 50;
+	pub const ListingChallengePeriod: u64 = 5;
+	pub const MaxClaimsCleared: u32 = 100;
+}
+
+impl system::Trait for Test {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = ();
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u128>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for Test {
+	type MaxLocks = ();
+	type Balance = u128;
+	type Event = ();
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+/// Only `BUYER` has passed KYC.
+pub struct Kyc;
+
+impl pallet_token::KycProvider<u64> for Kyc {
+	fn kyc_level(who: &u64) -> u8 {
+		if *who == BUYER { 1 } else { 0 }
+	}
+}
+
+impl pallet_token::Trait for Test {
+	type Event = ();
+	type Currency = Balances;
+	type TransferHook = ();
+	type KycProvider = Kyc;
+	type TokenDeposit = ();
+	type MetadataDepositPerByte = ();
+	type RegistryOrigin = system::EnsureRoot<u64>;
+	type UniqueSymbols = UniqueSymbols;
+	type SnapshotExcluded = ();
+	type EscrowAccounts = EscrowAccounts;
+	type MaxClaimsCleared = MaxClaimsCleared;
+	type MinVestedShare = ();
+}
+
+impl Trait for Test {
+	type Event = ();
+	type Currency = Balances;
+	type MaxOpenOrdersPerUser = MaxOpenOrdersPerUser;
+	type MaxOrdersPerSide = MaxOrdersPerSide;
+	type MaxTriggersPerMarket = MaxTriggersPerMarket;
+	type MaxMatchingSteps = MaxMatchingSteps;
+	type OrderDeposit = OrderDeposit;
+	type ListingOrigin = system::EnsureRoot<u64>;
+	type FeeOrigin = system::EnsureRoot<u64>;
+	type AdminOrigin = system::EnsureRoot<u64>;
+	type ListingBond = ListingBond;
+	type ListingChallengePeriod = ListingChallengePeriod;
+	type CandlePeriod = CandlePeriod;
+	type VolumeWindow = VolumeWindow;
+	type OracleGranularity = OracleGranularity;
+	type OracleObservations = OracleObservations;
+}
+
+pub type System = system::Module<Test>;
+pub type Balances = balances::Module<Test>;
+pub type Token = pallet_token::Module<Test>;
+pub type Exchange = Module<Test>;
+
+pub const OPERATION: u64 = 100;
+pub const VAULT: u64 = 101;
+pub const BUYER: u64 = 1;
+pub const SELLER: u64 = 2;
+pub const OTHER: u64 = 3;
+
+/// Starting native balance of `BUYER`, `SELLER` and `OTHER`.
+pub const ENDOWMENT: u128 = 1_000_000_000_000_000;
+
+/// `OPERATION` and `VAULT` start with the existential deposit, as on a live chain.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	test_ext(vec![(OPERATION, 1), (VAULT, 1)])
+}
+
+/// `OPERATION` and `VAULT` start without any native balance.
+pub fn new_unendowed_test_ext() -> sp_io::TestExternalities {
+	test_ext(vec![])
+}
+
+fn test_ext(exchange_accounts: Vec<(u64, u128)>) -> sp_io::TestExternalities {
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	let mut balances = vec![(BUYER, ENDOWMENT), (SELLER, ENDOWMENT), (OTHER, ENDOWMENT)];
+	balances.extend(exchange_accounts);
+	balances::GenesisConfig::<Test> { balances }.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
@@ -1,23 +1,908 @@
-use crate::{Error, mock::*};
-use frame_support::{assert_ok, assert_noop};
+use crate::{mock::*, BuyOrderNativeList, Candle, Error, ExchangeAccount, ExchangeParam, PairLimits, Market, Oracle, OrderSide, TriggerKind, OpenOrders, BuyOrder, BuyOrderList, BuyOrderNative, SellOrder, SellOrderList, SellOrderNative, TriggerOrder, PRICE_SCALE};
+use frame_support::{assert_noop, assert_ok, traits::OnFinalize};
+use pallet_token::VestingInfo;
+use sp_runtime::{traits::BadOrigin, Permill};
+
+const SUPPLY: u128 = 1_000_000_000_000_000_000;
+
+/// Small xorshift generator so the property tests below are reproducible.
+struct Rng(u64);
+
+impl Rng {
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0
+	}
+
+	fn range(&mut self, low: u128, high: u128) -> u128 {
+		low + (self.next() as u128) % (high - low)
+	}
+}
+
+/// Token 0 (base) is held by `BUYER`, token 1 (target) by `SELLER`, and pair 0 and
+/// native pair 0 both trade token 1.
+fn setup() {
+	assert_ok!(Exchange::set_accounts(Origin::root(), ExchangeAccount::Operation, OPERATION));
+	assert_ok!(Exchange::set_accounts(Origin::root(), ExchangeAccount::Vault, VAULT));
+	assert_ok!(Token::create(Origin::signed(BUYER), b"BASE".to_vec(), b"BASE".to_vec(), SUPPLY));
+	assert_ok!(Token::create(Origin::signed(SELLER), b"TGT".to_vec(), b"TGT".to_vec(), SUPPLY));
+	assert_ok!(Exchange::create_pair(Origin::root(), 0, 1));
+	assert_ok!(Exchange::create_native_pair(Origin::root(), 1));
+}
+
+#[test]
+fn fill_never_exceeds_either_escrow() {
+	let mut rng = Rng(0x5eed_1234_abcd_0001);
+
+	for _ in 0..10_000 {
+		let buy_volume = rng.range(0, u64::max_value() as u128);
+		let sell_volume = rng.range(0, u64::max_value() as u128);
+		let ratio = rng.range(1, PRICE_SCALE * 1_000_000);
+
+		let (base_volume, target_volume) = Exchange::fill(buy_volume, sell_volume, ratio);
+
+		assert!(base_volume <= buy_volume);
+		assert!(target_volume <= sell_volume);
+		// The buyer never pays more than `ratio` per unit of target.
+		assert!(base_volume * PRICE_SCALE <= target_volume * ratio);
+		// The fill is maximal: either the seller is exhausted or one more unit
+		// of target would cost more than the buyer has left.
+		assert!(
+			target_volume == sell_volume ||
+			(target_volume + 1) * ratio > buy_volume * PRICE_SCALE
+		);
+	}
+}
+
+#[test]
+fn settlement_conserves_assets() {
+	let mut rng = Rng(0x5eed_1234_abcd_0002);
+
+	for _ in 0..200 {
+		let buy_volume = rng.range(1, 1_000_000_000_000);
+		let sell_volume = rng.range(1, 1_000_000_000_000);
+		let sell_ratio = rng.range(PRICE_SCALE / 100, PRICE_SCALE * 100);
+		let buy_ratio = rng.range(sell_ratio, sell_ratio * 2);
+
+		new_test_ext().execute_with(|| {
+			setup();
+			assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, buy_volume, buy_ratio));
+			assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, sell_volume, sell_ratio));
+
+			Exchange::on_finalize(1);
+
+			let remaining_buy = <BuyOrder<Test>>::get((0, 0)).volume;
+			let remaining_sell = <SellOrder<Test>>::get((0, 0)).volume;
+
+			// Base: escrowed = paid to seller + fees + remaining.
+			let seller_base = Token::get_balance(0, SELLER);
+			let vault_base = Token::get_balance(0, VAULT);
+			assert_eq!(buy_volume, seller_base + vault_base + remaining_buy);
+			assert_eq!(Token::get_balance(0, OPERATION), remaining_buy);
+			assert_eq!(Token::get_balance(0, BUYER), SUPPLY - buy_volume);
+
+			// Target: escrowed = paid to buyer + fees + remaining.
+			let buyer_target = Token::get_balance(1, BUYER);
+			let vault_target = Token::get_balance(1, VAULT);
+			assert_eq!(sell_volume, buyer_target + vault_target + remaining_sell);
+			assert_eq!(Token::get_balance(1, OPERATION), remaining_sell);
+			assert_eq!(Token::get_balance(1, SELLER), SUPPLY - sell_volume);
+
+			// Settlement ran at the buyer's limit.
+			let base_paid = buy_volume - remaining_buy;
+			let target_paid = sell_volume - remaining_sell;
+			assert!(base_paid * PRICE_SCALE <= target_paid * buy_ratio);
+		});
+	}
+}
+
+#[test]
+fn native_settlement_conserves_assets() {
+	let mut rng = Rng(0x5eed_1234_abcd_0003);
+
+	for _ in 0..200 {
+		let buy_volume = rng.range(1, 1_000_000_000_000);
+		let sell_volume = rng.range(1, 1_000_000_000_000);
+		let sell_ratio = rng.range(PRICE_SCALE / 100, PRICE_SCALE * 100);
+		let buy_ratio = rng.range(sell_ratio, sell_ratio * 2);
+
+		new_test_ext().execute_with(|| {
+			setup();
+			assert_ok!(Exchange::buy_native(Origin::signed(BUYER), 0, buy_volume, buy_ratio));
+			assert_ok!(Exchange::sell_native(Origin::signed(SELLER), 0, sell_volume, sell_ratio));
+
+			Exchange::on_finalize(1);
+
+			let remaining_buy = <BuyOrderNative<Test>>::get((0, 0)).volume;
+			let remaining_sell = <SellOrderNative<Test>>::get((0, 0)).volume;
+
+			// Order deposits are only reserved, so compare total balances.
+			let seller_base = Balances::total_balance(&SELLER) - ENDOWMENT;
+			let vault_base = Balances::free_balance(VAULT) - 1;
+			assert_eq!(buy_volume, seller_base + vault_base + remaining_buy);
+			assert_eq!(Balances::free_balance(OPERATION) - 1, remaining_buy);
+			assert_eq!(Balances::total_balance(&BUYER), ENDOWMENT - buy_volume);
+
+			let buyer_target = Token::get_balance(1, BUYER);
+			let vault_target = Token::get_balance(1, VAULT);
+			assert_eq!(sell_volume, buyer_target + vault_target + remaining_sell);
+			assert_eq!(Token::get_balance(1, OPERATION), remaining_sell);
+		});
+	}
+}
+
+#[test]
+fn native_escrow_is_never_lost_by_unendowed_exchange_accounts() {
+	new_unendowed_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::buy_native(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell_native(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		assert_eq!(Balances::free_balance(OPERATION), 10_000);
+
+		// Paying out the whole escrow would reap the operation account, so nothing settles
+		// and nothing moves.
+		Exchange::on_finalize(1);
+		assert_eq!(<BuyOrderNative<Test>>::get((0, 0)).volume, 10_000);
+		assert_eq!(<SellOrderNative<Test>>::get((0, 0)).volume, 10_000);
+		assert_eq!(Balances::free_balance(OPERATION), 10_000);
+		assert_eq!(Balances::total_balance(&SELLER), ENDOWMENT);
+		assert!(Exchange::cancel_native_buy(Origin::signed(BUYER), 0, 0).is_err());
+		assert_eq!(Balances::free_balance(OPERATION), 10_000);
+		assert!(<BuyOrderNativeList>::contains_key(0, 0));
+
+		// Once it holds the existential deposit the orders settle.
+		assert_ok!(Balances::transfer(Origin::signed(OTHER), OPERATION, 1));
+		Exchange::on_finalize(2);
+		assert_eq!(Balances::free_balance(OPERATION), 1);
+		assert_eq!(Balances::total_balance(&SELLER), ENDOWMENT + 9_990);
+		assert_eq!(Balances::free_balance(VAULT), 10);
+	});
+}
+
+#[test]
+fn dust_left_after_a_fill_is_refunded() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::set_pair_limits(Origin::root(), Market::Pair(0), PairLimits {
+			min_amount: 1_000,
+			min_notional: 1_000,
+			..Default::default()
+		}));
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 9_500, PRICE_SCALE));
+
+		Exchange::on_finalize(1);
+
+		// 500 base is below the minimum notional, so it goes back to the buyer.
+		assert!(!<BuyOrderList>::contains_key(0, 0));
+		assert!(!<SellOrderList>::contains_key(0, 0));
+		assert_eq!(Token::get_balance(0, OPERATION), 0);
+		assert_eq!(Token::get_balance(0, BUYER), SUPPLY - 9_500);
+		assert_eq!(Token::get_balance(1, OPERATION), 0);
+	});
+}
+
+#[test]
+fn remainder_too_small_to_buy_anything_is_refunded() {
+	new_test_ext().execute_with(|| {
+		setup();
+		// Default limits are zero. 3 base buys 1 target, so 10_000 base fills 3_333 target
+		// and leaves 1 base that buys nothing.
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, 3 * PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 3_333, 3 * PRICE_SCALE));
+
+		Exchange::on_finalize(1);
+
+		assert!(!<BuyOrderList>::contains_key(0, 0));
+		assert_eq!(Token::get_balance(0, OPERATION), 0);
+		assert_eq!(Token::get_balance(0, BUYER), SUPPLY - 9_999);
+		assert_eq!(Balances::reserved_balance(BUYER), 0);
+	});
+}
+
+#[test]
+fn native_buy_volume_cannot_overflow_with_the_deposit() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(
+			Exchange::buy_native(Origin::signed(BUYER), 0, u128::max_value(), PRICE_SCALE),
+			Error::<Test>::InsufficientAmount
+		);
+	});
+}
+
+#[test]
+fn order_deposit_is_held_until_the_order_closes() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_eq!(Balances::reserved_balance(BUYER), 10);
+		assert_eq!(<OpenOrders<Test>>::get(BUYER), 1);
+
+		assert_ok!(Exchange::cancel_buy(Origin::signed(BUYER), 0, 0));
+		assert_eq!(Balances::reserved_balance(BUYER), 0);
+		assert_eq!(<OpenOrders<Test>>::get(BUYER), 0);
+
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		Exchange::on_finalize(1);
+		assert_eq!(Balances::reserved_balance(BUYER), 0);
+		assert_eq!(Balances::reserved_balance(SELLER), 0);
+	});
+}
+
+#[test]
+fn frozen_accounts_cannot_trade() {
+	new_test_ext().execute_with(|| {
+		setup();
+		// The seller could not be paid in base, so it cannot sell for it.
+		assert_ok!(Token::freeze(Origin::signed(BUYER), SELLER, 0));
+		assert_noop!(
+			Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE),
+			Error::<Test>::CannotReceiveToken
+		);
+
+		// Only the unfrozen part of a balance can be escrowed.
+		assert_ok!(Token::freeze_balance(Origin::signed(BUYER), BUYER, 0, SUPPLY - 10_000));
+		assert_noop!(
+			Exchange::buy(Origin::signed(BUYER), 0, 10_001, PRICE_SCALE),
+			Error::<Test>::InsufficientAmount
+		);
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+	});
+}
+
+#[test]
+fn vesting_balances_cannot_be_escrowed() {
+	new_test_ext().execute_with(|| {
+		setup();
+		System::set_block_number(1);
+		let schedule = VestingInfo { locked: 10_000, per_block: 1_000, starting_block: 1, cliff: 100 };
+		assert_ok!(Token::vested_transfer(Origin::signed(BUYER), 0, OTHER, schedule));
+		assert_noop!(
+			Exchange::buy(Origin::signed(OTHER), 0, 10_000, PRICE_SCALE),
+			Error::<Test>::InsufficientAmount
+		);
+	});
+}
+
+#[test]
+fn markets_can_require_a_kyc_level() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(
+			Exchange::set_required_kyc(Origin::signed(BUYER), Market::Pair(0), 1),
+			BadOrigin
+		);
+		assert_ok!(Exchange::set_required_kyc(Origin::root(), Market::Pair(0), 1));
+
+		assert_noop!(
+			Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE),
+			Error::<Test>::InsufficientKyc
+		);
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+	});
+}
+
+#[test]
+fn restricted_tokens_only_trade_between_whitelisted_accounts() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::set_restricted(Origin::signed(SELLER), 1, true));
+		assert_noop!(
+			Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE),
+			Error::<Test>::CannotReceiveToken
+		);
+
+		// Escrow needs the exchange account on the whitelist too.
+		for who in &[BUYER, SELLER, OPERATION, VAULT] {
+			assert_ok!(Token::whitelist(Origin::signed(SELLER), 1, *who));
+		}
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		Exchange::on_finalize(1);
+		assert_eq!(Token::get_balance(1, BUYER), 9_990);
+	});
+}
+
+#[test]
+fn tokens_requiring_kyc_trade_between_verified_accounts() {
+	new_test_ext().execute_with(|| {
+		setup();
+		// Only the buyer is verified for the target token, which the seller still sells.
+		assert_ok!(Token::set_required_kyc(Origin::signed(SELLER), 1, 1));
+		assert!(!Token::can_receive(1, &SELLER));
+
+		// The operation and vault accounts are exempt, so escrow and fees still move.
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		Exchange::on_finalize(1);
+		assert_eq!(Token::get_balance(1, BUYER), 9_990);
+		assert_eq!(Token::get_balance(1, OPERATION), 0);
+	});
+}
 
 #[test]
-fn it_works_for_default_value() {
+fn orders_of_an_account_frozen_after_placing_them_do_not_settle() {
 	new_test_ext().execute_with(|| {
-		// Dispatch a signed extrinsic.
-		assert_ok!(TemplateModule::do_something(Origin::signed(1), 42));
-		// Read pallet storage and assert an expected result.
-		assert_eq!(TemplateModule::something(), Some(42));
+		setup();
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Token::freeze(Origin::signed(SELLER), BUYER, 1));
+
+		Exchange::on_finalize(1);
+
+		// Nothing moved and both orders still rest on the book.
+		assert!(<BuyOrderList>::contains_key(0, 0));
+		assert!(<SellOrderList>::contains_key(0, 0));
+		assert_eq!(Token::get_balance(0, OPERATION), 10_000);
+		assert_eq!(Token::get_balance(1, OPERATION), 10_000);
+		assert_eq!(Exchange::last_price(Market::Pair(0)), None);
+
+		assert_ok!(Token::thaw(Origin::signed(SELLER), BUYER, 1));
+		Exchange::on_finalize(2);
+		assert!(!<BuyOrderList>::contains_key(0, 0));
+		assert_eq!(Token::get_balance(1, BUYER), 9_990);
 	});
 }
 
 #[test]
-fn correct_error_for_none_value() {
+fn exchange_accounts_cannot_be_frozen() {
 	new_test_ext().execute_with(|| {
-		// Ensure the expected error is thrown when no value is present.
+		setup();
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+
+		// Neither the token owner nor a freezer can lock the escrow away from traders.
+		assert_noop!(
+			Token::freeze(Origin::signed(BUYER), OPERATION, 0),
+			pallet_token::Error::<Test>::EscrowAccount
+		);
 		assert_noop!(
-			TemplateModule::cause_error(Origin::signed(1)),
-			Error::<Test>::NoneValue
+			Token::freeze_balance(Origin::signed(BUYER), OPERATION, 0, 10_000),
+			pallet_token::Error::<Test>::EscrowAccount
+		);
+		assert_noop!(
+			Token::freeze(Origin::signed(SELLER), VAULT, 1),
+			pallet_token::Error::<Test>::EscrowAccount
+		);
+
+		assert_ok!(Exchange::cancel_buy(Origin::signed(BUYER), 0, 0));
+		assert_eq!(Token::get_balance(0, BUYER), SUPPLY);
+		assert_eq!(Token::get_balance(0, OPERATION), 0);
+	});
+}
+
+#[test]
+fn orders_match_best_price_first() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, 2 * PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 5_000, PRICE_SCALE));
+		assert_eq!(
+			Exchange::order_book(Market::Pair(0), OrderSide::Buy),
+			vec![(2 * PRICE_SCALE, 1), (PRICE_SCALE, 0)]
 		);
+
+		Exchange::on_finalize(1);
+
+		// The higher bid takes the whole sell order, at its own ratio.
+		assert!(!<BuyOrderList>::contains_key(0, 1));
+		assert_eq!(<BuyOrder<Test>>::get((0, 0)).volume, 10_000);
+		assert_eq!(Exchange::last_price(Market::Pair(0)), Some(2 * PRICE_SCALE));
+		assert_eq!(Exchange::order_book(Market::Pair(0), OrderSide::Buy), vec![(PRICE_SCALE, 0)]);
+		assert!(Exchange::order_book(Market::Pair(0), OrderSide::Sell).is_empty());
+	});
+}
+
+#[test]
+fn matching_carries_over_once_its_steps_run_out() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer_(0, BUYER, OTHER, 1_000));
+		for _ in 0..3 {
+			assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, PRICE_SCALE));
+		}
+		assert_ok!(Exchange::buy(Origin::signed(OTHER), 0, 1_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 4_000, PRICE_SCALE));
+
+		// Visiting the market and three fills take all four steps of the block.
+		Exchange::on_finalize(1);
+		assert!(!<BuyOrderList>::contains_key(0, 2));
+		assert!(<BuyOrderList>::contains_key(0, 3));
+		assert_eq!(<SellOrder<Test>>::get((0, 0)).volume, 1_000);
+		assert_eq!(Exchange::markets_to_match(), vec![Market::Pair(0)]);
+
+		Exchange::on_finalize(2);
+		assert!(!<BuyOrderList>::contains_key(0, 3));
+		assert!(!<SellOrderList>::contains_key(0, 0));
+		assert_eq!(Token::get_balance(1, OTHER), 999);
+		assert!(Exchange::markets_to_match().is_empty());
+	});
+}
+
+#[test]
+fn open_orders_are_bounded() {
+	new_test_ext().execute_with(|| {
+		setup();
+		for _ in 0..3 {
+			assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, PRICE_SCALE));
+		}
+		assert_noop!(
+			Exchange::buy(Origin::signed(BUYER), 0, 1_000, PRICE_SCALE),
+			Error::<Test>::TooManyOpenOrders
+		);
+
+		assert_ok!(Token::transfer_(0, BUYER, OTHER, 1_000));
+		assert_ok!(Token::transfer_(0, BUYER, SELLER, 1_000));
+		assert_ok!(Exchange::buy(Origin::signed(OTHER), 0, 1_000, PRICE_SCALE));
+		assert_noop!(
+			Exchange::buy(Origin::signed(SELLER), 0, 1_000, PRICE_SCALE),
+			Error::<Test>::OrderBookFull
+		);
+	});
+}
+
+#[test]
+fn stop_order_joins_the_book_when_the_price_crosses() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		Exchange::on_finalize(1);
+		assert_eq!(Exchange::last_price(Market::Pair(0)), Some(PRICE_SCALE));
+
+		// Sell stop below the last price, escrowed at placement.
+		let target_before = Token::get_balance(1, SELLER);
+		assert_noop!(
+			Exchange::place_trigger(Origin::signed(SELLER), Market::Pair(0), OrderSide::Sell,
+				TriggerKind::StopLimit, 2 * PRICE_SCALE, PRICE_SCALE / 2, 5_000),
+			Error::<Test>::TriggerAlreadyReached
+		);
+		assert_ok!(Exchange::place_trigger(Origin::signed(SELLER), Market::Pair(0), OrderSide::Sell,
+			TriggerKind::StopLimit, PRICE_SCALE / 2, PRICE_SCALE / 4, 5_000));
+		assert_eq!(Token::get_balance(1, SELLER), target_before - 5_000);
+		assert!(<TriggerOrder<Test>>::contains_key(Market::Pair(0), 0));
+
+		// A trade at 0.5 crosses the stop, which rests at its limit the same block.
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE / 2));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 20_000, PRICE_SCALE / 2));
+		Exchange::on_finalize(2);
+
+		assert!(!<TriggerOrder<Test>>::contains_key(Market::Pair(0), 0));
+		assert!(<SellOrderList>::contains_key(0, 2));
+		assert_eq!(<SellOrder<Test>>::get((0, 2)).volume, 5_000);
+	});
+}
+
+#[test]
+fn cancelled_trigger_order_is_refunded() {
+	new_test_ext().execute_with(|| {
+		setup();
+		let before = Token::get_balance(0, BUYER);
+		assert_ok!(Exchange::place_trigger(Origin::signed(BUYER), Market::Pair(0), OrderSide::Buy,
+			TriggerKind::StopMarket, 2 * PRICE_SCALE, 0, 5_000));
+		assert_eq!(Balances::reserved_balance(BUYER), 10);
+		assert_noop!(
+			Exchange::cancel_trigger(Origin::signed(SELLER), Market::Pair(0), 0),
+			Error::<Test>::NotOrderCreator
+		);
+
+		assert_ok!(Exchange::cancel_trigger(Origin::signed(BUYER), Market::Pair(0), 0));
+		assert_eq!(Token::get_balance(0, BUYER), before);
+		assert_eq!(Balances::reserved_balance(BUYER), 0);
+		assert_eq!(<OpenOrders<Test>>::get(BUYER), 0);
+	});
+}
+
+#[test]
+fn trigger_orders_are_bounded_per_market() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::place_trigger(Origin::signed(BUYER), Market::Pair(0), OrderSide::Buy,
+			TriggerKind::StopMarket, 2 * PRICE_SCALE, 0, 1_000));
+		assert_ok!(Exchange::place_trigger(Origin::signed(SELLER), Market::Pair(0), OrderSide::Sell,
+			TriggerKind::StopMarket, PRICE_SCALE / 2, 0, 1_000));
+		assert_eq!(Exchange::trigger_order_open_count(Market::Pair(0)), 2);
+		assert_noop!(
+			Exchange::place_trigger(Origin::signed(BUYER), Market::Pair(0), OrderSide::Buy,
+				TriggerKind::StopMarket, 2 * PRICE_SCALE, 0, 1_000),
+			Error::<Test>::TooManyTriggers
+		);
+
+		// Other markets have their own allowance.
+		assert_ok!(Exchange::place_trigger(Origin::signed(SELLER), Market::Native(0), OrderSide::Sell,
+			TriggerKind::StopMarket, PRICE_SCALE / 2, 0, 1_000));
+
+		assert_ok!(Exchange::cancel_trigger(Origin::signed(BUYER), Market::Pair(0), 0));
+		assert_eq!(Exchange::trigger_order_open_count(Market::Pair(0)), 1);
+		assert_ok!(Exchange::place_trigger(Origin::signed(BUYER), Market::Pair(0), OrderSide::Buy,
+			TriggerKind::StopMarket, 2 * PRICE_SCALE, 0, 1_000));
+	});
+}
+
+#[test]
+fn crossed_trigger_waits_while_its_side_is_full() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer_(1, SELLER, OTHER, 100_000));
+		assert_ok!(Exchange::place_trigger(Origin::signed(SELLER), Market::Pair(0), OrderSide::Sell,
+			TriggerKind::StopLimit, PRICE_SCALE / 2, PRICE_SCALE / 4, 5_000));
+
+		// Fill the sell side: three orders far above the market and one at 0.5.
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 1_000, 4 * PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 1_000, 4 * PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(OTHER), 0, 1_000, 4 * PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(OTHER), 0, 5_000, PRICE_SCALE / 2));
+		assert_eq!(Exchange::open_order_count(Market::Pair(0), OrderSide::Sell), 4);
+
+		// A partial fill at 0.5 crosses the trigger but leaves the side full.
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, PRICE_SCALE / 2));
+		Exchange::on_finalize(1);
+		assert_eq!(Exchange::last_price(Market::Pair(0)), Some(PRICE_SCALE / 2));
+		assert!(<TriggerOrder<Test>>::contains_key(Market::Pair(0), 0));
+		assert_eq!(Exchange::open_order_count(Market::Pair(0), OrderSide::Sell), 4);
+
+		// Once a slot frees up, the next trade activates it.
+		assert_ok!(Exchange::cancel_sell(Origin::signed(OTHER), 0, 2));
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, PRICE_SCALE / 2));
+		Exchange::on_finalize(2);
+		assert!(!<TriggerOrder<Test>>::contains_key(Market::Pair(0), 0));
+		assert_eq!(Exchange::trigger_order_open_count(Market::Pair(0)), 0);
+		assert_eq!(Exchange::open_order_count(Market::Pair(0), OrderSide::Sell), 4);
+	});
+}
+
+/// Trade 1_000 base on pair 0 at `ratio` in `block`, leaving the book empty.
+fn trade(block: u64, ratio: u128) {
+	System::set_block_number(block);
+	assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, ratio));
+	assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 1_000_000, ratio));
+	Exchange::on_finalize(block);
+	let sell_id = Exchange::sell_order_count(0) - 1;
+	assert_ok!(Exchange::cancel_sell(Origin::signed(SELLER), 0, sell_id));
+}
+
+#[test]
+fn trades_roll_into_candles() {
+	new_test_ext().execute_with(|| {
+		setup();
+		trade(11, 2 * PRICE_SCALE);
+		trade(12, 4 * PRICE_SCALE);
+		trade(15, PRICE_SCALE);
+		trade(25, 2 * PRICE_SCALE);
+
+		let candles = Exchange::candles(Market::Pair(0), 0, 29);
+		assert_eq!(candles.len(), 2);
+		assert_eq!(candles[0], Candle {
+			start: 10,
+			open: 2 * PRICE_SCALE,
+			high: 4 * PRICE_SCALE,
+			low: PRICE_SCALE,
+			close: PRICE_SCALE,
+			volume: 500 + 250 + 1_000,
+		});
+		assert_eq!(candles[1].start, 20);
+		assert_eq!(candles[1].volume, 500);
+		assert_eq!(Exchange::last_price(Market::Pair(0)), Some(2 * PRICE_SCALE));
+		assert_eq!(Exchange::volume_24h(Market::Pair(0)), 2_250);
+
+		// The first candle leaves the window once it is entirely older than it.
+		System::set_block_number(49);
+		assert_eq!(Exchange::volume_24h(Market::Pair(0)), 500);
+		assert!(Exchange::candles(Market::Native(0), 0, 49).is_empty());
+	});
+}
+
+#[test]
+fn twap_weights_prices_by_blocks_held() {
+	new_test_ext().execute_with(|| {
+		setup();
+		trade(1, 2 * PRICE_SCALE);
+		trade(6, 4 * PRICE_SCALE);
+
+		System::set_block_number(11);
+		assert_eq!(Exchange::twap(Market::Pair(0), 10), Some(3 * PRICE_SCALE));
+		assert_eq!(Exchange::twap(Market::Pair(0), 5), Some(4 * PRICE_SCALE));
+		assert_eq!(Exchange::twap(Market::Pair(0), 11), None);
+
+		// A trade in the current block does not move the average.
+		trade(11, 400 * PRICE_SCALE);
+		assert_eq!(<Exchange as Oracle<_, _, _>>::twap(Market::Pair(0), 10), Some(3 * PRICE_SCALE));
+	});
+}
+
+#[test]
+fn administration_requires_the_configured_origins() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(Exchange::create_pair(Origin::signed(OPERATION), 1, 0), BadOrigin);
+		assert_noop!(Exchange::create_native_pair(Origin::signed(OPERATION), 0), BadOrigin);
+		assert_noop!(Exchange::set_params(Origin::signed(OPERATION), vec![ExchangeParam::DefaultPairLimits(Default::default())]), BadOrigin);
+		assert_noop!(Exchange::set_accounts(Origin::signed(OPERATION), ExchangeAccount::Vault, OPERATION), BadOrigin);
+	});
+}
+
+#[test]
+fn pairs_are_only_listed_for_distinct_existing_tokens() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(Exchange::create_pair(Origin::root(), 1, 1), Error::<Test>::SameToken);
+		assert_noop!(Exchange::create_pair(Origin::root(), 0, 7), Error::<Test>::TokenNotFound);
+		assert_noop!(Exchange::create_pair(Origin::root(), 7, 0), Error::<Test>::TokenNotFound);
+		assert_noop!(Exchange::create_native_pair(Origin::root(), 7), Error::<Test>::TokenNotFound);
+
+		// A proposal whose token is destroyed during its challenge period is dropped.
+		assert_ok!(Token::create(Origin::signed(OTHER), b"GONE".to_vec(), b"GONE".to_vec(), 0));
+		System::set_block_number(1);
+		assert_ok!(Exchange::propose_native_pair(Origin::signed(OTHER), 2));
+		assert_ok!(Token::destroy(Origin::signed(OTHER), 2));
+		Exchange::on_finalize(6);
+		assert_eq!(Exchange::pair_native_count(), 1);
+		assert_eq!(Balances::reserved_balance(OTHER), 0);
+	});
+}
+
+#[test]
+fn pair_settings_require_a_listed_pair() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(Exchange::edit_native_pair(Origin::root(), 9, 0), Error::<Test>::PairNotFound);
+		assert_noop!(Exchange::edit_native_pair(Origin::root(), 0, 7), Error::<Test>::TokenNotFound);
+		assert_noop!(
+			Exchange::set_pair_limits(Origin::root(), Market::Pair(9), Default::default()),
+			Error::<Test>::PairNotFound
+		);
+		assert_noop!(Exchange::set_required_kyc(Origin::root(), Market::Native(9), 1), Error::<Test>::PairNotFound);
+
+		assert_ok!(Exchange::edit_native_pair(Origin::root(), 0, 0));
+		assert_eq!(Exchange::pair_native(0).target, 0);
+		assert_ok!(Exchange::set_required_kyc(Origin::root(), Market::Native(0), 1));
+	});
+}
+
+#[test]
+fn proposed_pair_lists_after_the_challenge_period() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(Exchange::propose_pair(Origin::signed(OTHER), 1, 1), Error::<Test>::SameToken);
+		assert_noop!(Exchange::propose_pair(Origin::signed(OTHER), 1, 7), Error::<Test>::TokenNotFound);
+
+		assert_ok!(Token::create(Origin::signed(OTHER), b"GONE".to_vec(), b"GONE".to_vec(), 0));
+		assert_ok!(Token::destroy(Origin::signed(OTHER), 2));
+		assert_noop!(Exchange::propose_pair(Origin::signed(OTHER), 2, 0), Error::<Test>::TokenNotFound);
+		assert_noop!(Exchange::propose_pair(Origin::signed(OTHER), 0, 2), Error::<Test>::TokenNotFound);
+
+		System::set_block_number(1);
+		assert_ok!(Exchange::propose_pair(Origin::signed(OTHER), 1, 0));
+		assert_eq!(Balances::reserved_balance(OTHER), 50);
+
+		Exchange::on_finalize(5);
+		assert_eq!(Exchange::pair_count(), 1);
+
+		Exchange::on_finalize(6);
+		assert_eq!(Exchange::pair_count(), 2);
+		assert_eq!(Exchange::pair(1).unwrap().base, 1);
+		assert_eq!(Balances::reserved_balance(OTHER), 0);
+		assert!(Exchange::pair_proposal(0).is_none());
+	});
+}
+
+#[test]
+fn rejected_pair_proposal_is_slashed_to_the_vault() {
+	new_test_ext().execute_with(|| {
+		setup();
+		System::set_block_number(1);
+		assert_ok!(Exchange::propose_native_pair(Origin::signed(OTHER), 0));
+		assert_noop!(Exchange::reject_pair(Origin::signed(OPERATION), 0), BadOrigin);
+
+		assert_ok!(Exchange::reject_pair(Origin::root(), 0));
+		assert_eq!(Balances::total_balance(&OTHER), ENDOWMENT - 50);
+		assert_eq!(Balances::free_balance(VAULT), 1 + 50);
+		assert_noop!(Exchange::reject_pair(Origin::root(), 0), Error::<Test>::ProposalNotFound);
+
+		Exchange::on_finalize(6);
+		assert_eq!(Exchange::pair_native_count(), 1);
+	});
+}
+
+#[test]
+fn unknown_admin_variants_do_not_decode() {
+	use parity_scale_codec::Decode;
+
+	assert_eq!(ExchangeAccount::decode(&mut &[1u8][..]).ok(), Some(ExchangeAccount::Vault));
+	assert!(ExchangeAccount::decode(&mut &[2u8][..]).is_err());
+	assert!(ExchangeParam::<u128>::decode(&mut &[1u8][..]).is_err());
+}
+
+#[test]
+fn orders_are_checked_against_their_pair_limits() {
+	new_test_ext().execute_with(|| {
+		setup();
+		let limits = PairLimits {
+			min_amount: 100,
+			min_notional: 1_000,
+			min_price: PRICE_SCALE / 2,
+			max_price: 2 * PRICE_SCALE,
+		};
+		assert_noop!(
+			Exchange::set_pair_limits(Origin::signed(OPERATION), Market::Pair(0), limits.clone()),
+			BadOrigin
+		);
+		assert_ok!(Exchange::set_pair_limits(Origin::root(), Market::Pair(0), limits.clone()));
+
+		assert_noop!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, PRICE_SCALE / 4), Error::<Test>::BelowMinimumRatio);
+		assert_noop!(Exchange::buy(Origin::signed(BUYER), 0, 1_000, 3 * PRICE_SCALE), Error::<Test>::AboveMaximumRatio);
+		assert_noop!(Exchange::buy(Origin::signed(BUYER), 0, 999, PRICE_SCALE), Error::<Test>::BelowMinimumNotional);
+		// Sell orders are sized in target and valued in base.
+		assert_noop!(Exchange::sell(Origin::signed(SELLER), 0, 99, PRICE_SCALE), Error::<Test>::BelowMinimumVolume);
+		assert_noop!(Exchange::sell(Origin::signed(SELLER), 0, 1_500, PRICE_SCALE / 2), Error::<Test>::BelowMinimumNotional);
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 2_000, PRICE_SCALE / 2));
+
+		// Other markets keep their own limits, and new listings take the defaults.
+		assert_ok!(Exchange::buy_native(Origin::signed(BUYER), 0, 1, PRICE_SCALE / 4));
+		assert_ok!(Exchange::set_params(Origin::root(), vec![ExchangeParam::DefaultPairLimits(limits.clone())]));
+		assert_ok!(Exchange::create_pair(Origin::root(), 1, 0));
+		assert_eq!(Exchange::pair_limits(Market::Pair(1)), limits);
+	});
+}
+
+#[test]
+fn trade_fee_is_set_by_fee_origin() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_eq!(Exchange::trade_fee(), Permill::from_parts(1_000));
+		assert_noop!(
+			Exchange::set_params(Origin::signed(OPERATION), vec![ExchangeParam::TradeFee(Permill::from_percent(1))]),
+			BadOrigin
+		);
+		assert_ok!(Exchange::set_params(Origin::root(), vec![ExchangeParam::TradeFee(Permill::from_percent(1))]));
+
+		assert_ok!(Exchange::buy(Origin::signed(BUYER), 0, 10_000, PRICE_SCALE));
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, PRICE_SCALE));
+		Exchange::on_finalize(1);
+		assert_eq!(Token::get_balance(0, SELLER), 9_900);
+		assert_eq!(Token::get_balance(1, BUYER), 9_900);
+		assert_eq!(Token::get_balance(0, VAULT), 100);
+		assert_eq!(Token::get_balance(1, VAULT), 100);
+
+		// Cancelling takes the same share of the remainder.
+		assert_ok!(Exchange::sell(Origin::signed(SELLER), 0, 10_000, 2 * PRICE_SCALE));
+		let before = Token::get_balance(1, SELLER);
+		assert_ok!(Exchange::cancel_sell(Origin::signed(SELLER), 0, 1));
+		assert_eq!(Token::get_balance(1, SELLER), before + 9_900);
+		assert_eq!(Token::get_balance(1, VAULT), 200);
+	});
+}
+
+#[test]
+fn genesis_config_sets_accounts_and_lists_pairs() {
+	use sp_runtime::BuildStorage;
+
+	let limits = PairLimits { min_amount: 5, ..Default::default() };
+	let storage = crate::GenesisConfig::<Test> {
+		account_operation: OPERATION,
+		account_vault: VAULT,
+		default_pair_limits: limits.clone(),
+		pairs: vec![(0, 1), (1, 0)],
+		native_pairs: vec![1],
+	}.build_storage().unwrap();
+
+	sp_io::TestExternalities::from(storage).execute_with(|| {
+		assert_eq!(Exchange::account_operation(), OPERATION);
+		assert_eq!(Exchange::account_vault(), VAULT);
+		assert_eq!(Exchange::pair_count(), 2);
+		assert_eq!(Exchange::pair(1).unwrap().base, 1);
+		assert_eq!(Exchange::pair(1).unwrap().banker, OPERATION);
+		assert_eq!(Exchange::pair_native_count(), 1);
+		assert_eq!(Exchange::pair_native(0).target, 1);
+		assert_eq!(Exchange::pair_limits(Market::Native(0)), limits);
+		assert_eq!(crate::StorageVersion::get(), crate::Releases::V2);
+	});
+}
+
+#[test]
+fn v0_storage_migrates_to_v1() {
+	use crate::{migrations::v1, Releases, StorageVersion};
+	use frame_support::{
+		storage::migration::{get_storage_value, put_storage_value},
+		traits::OnRuntimeUpgrade,
+		Blake2_128Concat, StorageHasher,
+	};
+	use parity_scale_codec::Encode;
+
+	fn put<K: Encode, V: Encode>(item: &[u8], key: K, value: V) {
+		put_storage_value(b"Exchange", item, &Blake2_128Concat::hash(&key.encode()), value);
+	}
+	fn order(order_id: u128, owner: u64, volume: u128, ratio: u128) -> v1::OldOrderInfo<u64, u128, u64> {
+		v1::OldOrderInfo { order_id, pair: 0, owner, volume, ratio, created: 1 }
+	}
+
+	new_test_ext().execute_with(|| {
+		setup();
+
+		// Buy order 1 was filled but, as in V0, never left the lists. V0 ratios are target
+		// per base, and buy order 2 asked for none, which has no inverse.
+		assert_ok!(Token::transfer(Origin::signed(BUYER), 0, OPERATION, 50));
+		put(b"BuyOrder", (0u128, 0u128), order(0, BUYER, 100, 2 * PRICE_SCALE));
+		put(b"BuyOrder", (0u128, 1u128), order(1, BUYER, 0, PRICE_SCALE));
+		put(b"BuyOrder", (0u128, 2u128), order(2, BUYER, 50, 0));
+		put(b"BuyOrderList", 0u128, vec![0u128, 1, 2]);
+		put(b"BuyOrderUserList", (0u128, BUYER), vec![0u128, 1, 2]);
+		put(b"SellOrderNative", (0u128, 0u128), order(0, SELLER, 200, 3 * PRICE_SCALE));
+		put(b"SellOrderNativeList", 0u128, vec![0u128]);
+		put(b"SellOrderNativeUserList", (0u128, SELLER), vec![0u128]);
+		put_storage_value(b"Exchange", b"MinimumVolume", &[], 7u128);
+		put_storage_value(b"Exchange", b"MinimumRatio", &[], 3u128);
+
+		assert_eq!(StorageVersion::get(), Releases::V0);
+		let open = v1::pre_migrate::<Test>().unwrap();
+		assert_eq!(open, 2);
+
+		Exchange::on_runtime_upgrade();
+
+		assert_eq!(v1::post_migrate::<Test>(open), Ok(()));
+		assert_eq!(StorageVersion::get(), Releases::V2);
+
+		assert_eq!(Exchange::buy_order((0, 0)).volume, 100);
+		assert_eq!(Exchange::buy_order((0, 0)).deposit, 0);
+		// Inverted into base per target, down for a buy and up for a sell.
+		assert_eq!(Exchange::buy_order((0, 0)).ratio, PRICE_SCALE / 2);
+		assert_eq!(Exchange::sell_order_native((0, 0)).ratio, PRICE_SCALE / 3 + 1);
+		assert!(!BuyOrder::<Test>::contains_key((0, 1)));
+		assert!(BuyOrderList::contains_key(0, 0));
+		assert!(!BuyOrderList::contains_key(0, 1));
+		// Order 2 was refunded and closed.
+		assert!(!BuyOrder::<Test>::contains_key((0, 2)));
+		assert!(!BuyOrderList::contains_key(0, 2));
+		assert_eq!(Token::get_balance(0, BUYER), SUPPLY);
+		assert_eq!(Token::get_balance(0, OPERATION), 0);
+		assert_eq!(Exchange::buy_order_open_count(0), 1);
+		assert_eq!(Exchange::sell_order_native((0, 0)).seller, SELLER);
+		assert_eq!(Exchange::sell_order_native_open_count(0), 1);
+		assert_eq!(OpenOrders::<Test>::get(BUYER), 1);
+		assert_eq!(OpenOrders::<Test>::get(SELLER), 1);
+		assert_eq!(Exchange::order_book(Market::Pair(0), OrderSide::Buy), vec![(PRICE_SCALE / 2, 0)]);
+		assert_eq!(Exchange::order_book(Market::Native(0), OrderSide::Sell), vec![(PRICE_SCALE / 3 + 1, 0)]);
+		assert_eq!(Exchange::markets_to_match(), vec![Market::Pair(0), Market::Native(0)]);
+
+		let limits = PairLimits { min_amount: 7, min_notional: 7, min_price: 3, max_price: 0 };
+		assert_eq!(Exchange::pair_limits(Market::Pair(0)), limits);
+		assert_eq!(Exchange::pair_limits(Market::Native(0)), limits);
+		assert_eq!(Exchange::default_pair_limits(), limits);
+		assert_eq!(get_storage_value::<u128>(b"Exchange", b"MinimumVolume", &[]), None);
+
+		// Running the upgrade again is a no-op.
+		Exchange::on_runtime_upgrade();
+		assert_eq!(Exchange::buy_order_open_count(0), 1);
+	});
+}
+
+#[test]
+fn v1_storage_migrates_to_v2() {
+	use crate::{migrations::v2, Releases, StorageVersion, TriggerOrderOpenCount};
+	use frame_support::traits::OnRuntimeUpgrade;
+
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Exchange::place_trigger(Origin::signed(BUYER), Market::Pair(0), OrderSide::Buy,
+			TriggerKind::StopMarket, 2 * PRICE_SCALE, 0, 1_000));
+		assert_ok!(Exchange::place_trigger(Origin::signed(SELLER), Market::Pair(0), OrderSide::Sell,
+			TriggerKind::StopMarket, PRICE_SCALE / 2, 0, 1_000));
+		assert_ok!(Exchange::place_trigger(Origin::signed(SELLER), Market::Native(0), OrderSide::Sell,
+			TriggerKind::StopMarket, PRICE_SCALE / 2, 0, 1_000));
+
+		// V1 kept no per-market count.
+		TriggerOrderOpenCount::remove(Market::Pair(0));
+		TriggerOrderOpenCount::remove(Market::Native(0));
+		StorageVersion::put(Releases::V1);
+
+		Exchange::on_runtime_upgrade();
+
+		assert_eq!(v2::post_migrate::<Test>(), Ok(()));
+		assert_eq!(Exchange::trigger_order_open_count(Market::Pair(0)), 2);
+		assert_eq!(Exchange::trigger_order_open_count(Market::Native(0)), 1);
 	});
 }
@@ -0,0 +1,20 @@
+//! Runtime API definition for the token pallet.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use parity_scale_codec::Codec;
+use sp_std::prelude::*;
+
+pub use pallet_token::DistributionIndex;
+
+sp_api::decl_runtime_apis! {
+	pub trait TokenApi<AccountId, Balance> where
+		AccountId: Codec,
+		Balance: Codec,
+	{
+		/// What `who` can still claim from `distribution`.
+		fn pending_claim(distribution: DistributionIndex, who: AccountId) -> Balance;
+		/// Every open distribution `who` has something to claim from, with the amount.
+		fn pending_claims(who: AccountId) -> Vec<(DistributionIndex, Balance)>;
+	}
+}
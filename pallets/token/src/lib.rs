@@ -2,31 +2,142 @@
 
 use frame_support::{
 	decl_error, decl_event, decl_module, decl_storage, ensure, dispatch::DispatchResult,
+	transactional,
+	storage::IterableStorageDoubleMap,
+	storage::IterableStorageMap,
+	weights::Weight,
 	traits::{
 		Currency, 
+		EnsureOrigin,
+		ExistenceRequirement::AllowDeath,
+		Get,
 		ReservableCurrency, 
 	},
 };
 use frame_system::{self as system, ensure_signed, ensure_root};
 use parity_scale_codec::{Decode, Encode};
+use sp_runtime::{
+	ModuleId,
+	Permill,
+	RuntimeDebug,
+	helpers_128bit::multiply_by_rational,
+	traits::{AccountIdConversion, AtLeast32BitUnsigned, CheckedAdd, SaturatedConversion, Saturating, Zero},
+};
 use sp_std::prelude::*;
 
+pub mod migrations;
+
+#[cfg(test)]
+mod mock;
+
 #[cfg(test)]
 mod tests;
 
 pub trait Trait: system::Trait {
 	type Event: From<Event<Self>> + Into<<Self as system::Trait>::Event>;
 	type Currency: ReservableCurrency<Self::AccountId>;
+	/// Extra checks run before every transfer and mint.
+	type TransferHook: TransferHook<Self::AccountId, BalanceOf<Self>>;
+	/// Identity verification level of accounts, for tokens that require one.
+	type KycProvider: KycProvider<Self::AccountId>;
+	/// Reserved from the creator of every token.
+	type TokenDeposit: Get<BalanceOf<Self>>;
+	/// Reserved on top of `TokenDeposit` for each byte of a token's name and symbol.
+	type MetadataDepositPerByte: Get<BalanceOf<Self>>;
+	/// Reserves symbols for issuers.
+	type RegistryOrigin: EnsureOrigin<Self::Origin>;
+	/// Whether symbols are registered in `Symbols` and refused to a second token. When
+	/// false, tokens may share a symbol and only reservations are enforced.
+	type UniqueSymbols: Get<bool>;
+	/// Accounts whose balances belong to others, such as exchange escrow, and are left
+	/// out of snapshots. The `distribution_pool` always is.
+	type SnapshotExcluded: Get<Vec<Self::AccountId>>;
+	/// Accounts that hold tokens in escrow for others, such as the exchange's. They
+	/// cannot be frozen and need no KYC level, so that what they hold can always be
+	/// settled or returned.
+	type EscrowAccounts: Get<Vec<Self::AccountId>>;
+	/// Most claim records of expired distributions removed in one block.
+	type MaxClaimsCleared: Get<u32>;
+	/// Smallest share of a token's supply `vested_transfer` may lock, so a dust schedule
+	/// cannot take up an account's only vesting slot, whatever the token's decimals.
+	type MinVestedShare: Get<Permill>;
+}
+
+/// Checks a runtime adds to every movement of tokens, on top of this pallet's own.
+pub trait TransferHook<AccountId, Balance> {
+	/// Called before `value` of `token` moves from `from`, or is minted if `None`, to `to`.
+	fn on_transfer(token: TokenIndex, from: Option<&AccountId>, to: &AccountId, value: Balance) -> DispatchResult;
+}
+
+impl<AccountId, Balance> TransferHook<AccountId, Balance> for () {
+	fn on_transfer(_: TokenIndex, _: Option<&AccountId>, _: &AccountId, _: Balance) -> DispatchResult {
+		Ok(())
+	}
+}
+
+/// Source of the identity verification (KYC) level of accounts.
+pub trait KycProvider<AccountId> {
+	/// Verification level of `who`, higher is stronger; zero when it has none.
+	fn kyc_level(who: &AccountId) -> u8;
+}
+
+impl<AccountId> KycProvider<AccountId> for () {
+	fn kyc_level(_: &AccountId) -> u8 {
+		0
+	}
 }
 
 pub type TokenIndex = u32;
+pub type SnapshotIndex = u32;
+pub type DistributionIndex = u32;
+
+/// Holds the tokens paid out by distributions until they are claimed.
+const DISTRIBUTION_POOL: ModuleId = ModuleId(*b"dcb/dist");
+
+/// Longest `reference` a `redeem` may carry.
+pub const MAX_REFERENCE_LENGTH: usize = 256;
 
 type AccountIdOf<T> = <T as system::Trait>::AccountId;
 type BalanceOf<T> = <<T as Trait>::Currency as Currency<AccountIdOf<T>>>::Balance;
 type TokenInfoOf<T> = TokenInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
+type SnapshotInfoOf<T> = SnapshotInfo<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type VestingInfoOf<T> = VestingInfo<BalanceOf<T>, <T as system::Trait>::BlockNumber>;
+type DistributionInfoOf<T> = DistributionInfo<AccountIdOf<T>, BalanceOf<T>, <T as system::Trait>::BlockNumber>;
 //type LockedTokenInfoOf<T> = LockedTokenInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
 //type ReservedTokenInfoOf<T> = ReservedTokenInfo<AccountIdOf<T>, <T as system::Trait>::BlockNumber>;
 
+/// Storage layouts of this pallet, oldest first. See `migrations`.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum Releases {
+	/// Balances, allowances and freezes keyed by `(token, account)` tuples.
+	V0,
+	/// Token owner kept only in `TokenInfo`, plus token roles.
+	V1,
+	/// Balances in a double map keyed by token first, with holder counts.
+	V2,
+	/// Symbols of tokens registered in `Symbols`.
+	V3,
+	/// Roles, whitelists, allowances and freezes in double maps keyed by token first.
+	V4,
+}
+
+impl Default for Releases {
+	fn default() -> Self {
+		Releases::V0
+	}
+}
+
+/// Permissions the owner of a token can grant to other accounts. The owner holds all of them.
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub enum TokenRole {
+	Minter,
+	Burner,
+	Freezer,
+	Pauser,
+	/// Manages the whitelist of restricted tokens.
+	Compliance,
+}
+
 #[derive(Encode, Decode, Default, PartialEq, Eq)]
 #[cfg_attr(feature = "std", derive(Debug))]
 pub struct TokenInfo<AccountId, BlockNumber> {
@@ -36,6 +147,53 @@ pub struct TokenInfo<AccountId, BlockNumber> {
 	created: BlockNumber,
 }
 
+/// Balances of a token as they were at `block`, taken by `snapshot`.
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct SnapshotInfo<Balance, BlockNumber> {
+	pub token: TokenIndex,
+	pub block: BlockNumber,
+	/// Sum of the balances recorded, leaving out excluded accounts.
+	pub supply: Balance,
+	/// Holders recorded, leaving out excluded accounts.
+	pub holders: u32,
+}
+
+/// Part of a balance that unlocks over time. `per_block` unlocks every block from
+/// `starting_block`, but none of it can be spent before `cliff`. A pure cliff sets
+/// `per_block` to `locked`.
+#[derive(Encode, Decode, Default, Clone, Copy, PartialEq, Eq, RuntimeDebug)]
+pub struct VestingInfo<Balance, BlockNumber> {
+	pub locked: Balance,
+	pub per_block: Balance,
+	pub starting_block: BlockNumber,
+	pub cliff: BlockNumber,
+}
+
+impl<Balance: AtLeast32BitUnsigned + Copy, BlockNumber: AtLeast32BitUnsigned + Copy> VestingInfo<Balance, BlockNumber> {
+	/// Amount still locked at block `n`.
+	pub fn locked_at(&self, n: BlockNumber) -> Balance {
+		if n < self.cliff {
+			return self.locked;
+		}
+		let vested_blocks: u128 = n.saturating_sub(self.starting_block).saturated_into();
+		self.locked.saturating_sub(self.per_block.saturating_mul(vested_blocks.saturated_into()))
+	}
+}
+
+/// Funds shared out to the holders in a snapshot, in proportion to their balances.
+#[derive(Encode, Decode, Default, Clone, PartialEq, Eq, RuntimeDebug)]
+pub struct DistributionInfo<AccountId, Balance, BlockNumber> {
+	pub issuer: AccountId,
+	pub snapshot: SnapshotIndex,
+	/// Token paid out, or the native currency if `None`.
+	pub asset: Option<TokenIndex>,
+	pub amount: Balance,
+	/// Part of `amount` not claimed yet.
+	pub remaining: Balance,
+	/// Block at the end of which unclaimed funds go back to the issuer.
+	pub expires: BlockNumber,
+}
+
 // #[derive(Encode, Decode, Default, PartialEq, Eq)]
 // #[cfg_attr(feature = "std", derive(Debug))]
 // pub struct LockedTokenInfo<AccountId, BlockNumber> {
@@ -58,15 +216,76 @@ pub struct TokenInfo<AccountId, BlockNumber> {
 decl_storage! {
 	trait Store for Module<T: Trait> as TokenStore {
 
+		/// Layout storage is in. Chains that predate it are at `V0`.
+		pub StorageVersion build(|_: &GenesisConfig| Releases::V4): Releases;
+
 		pub Tokens get(fn tokens): map hasher(blake2_128_concat) TokenIndex => TokenInfoOf<T>;
 		pub TokenCount get(fn token_count): TokenIndex;
+		/// Native deposit held for a token, and the account it is reserved from.
+		pub Deposit get(fn deposit): map hasher(blake2_128_concat) TokenIndex => Option<(T::AccountId, BalanceOf<T>)>;
+		/// Token using each symbol, upper-cased. Empty without `UniqueSymbols`.
+		pub Symbols get(fn token_by_symbol): map hasher(blake2_128_concat) Vec<u8> => Option<TokenIndex>;
+		/// Symbols, upper-cased, only one issuer may use.
+		pub ReservedSymbols get(fn symbol_reservation): map hasher(blake2_128_concat) Vec<u8> => Option<T::AccountId>;
 
-		pub Balance get(fn balance): map hasher(blake2_128_concat) (u32, T::AccountId) => BalanceOf<T>;
-		pub Freezed get(fn freezed): map hasher(blake2_128_concat) (u32, T::AccountId) => bool;
+		/// Balances, keyed by token first so the holders of one token can be iterated.
+		pub Balance get(fn balance): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
+		/// Number of accounts with a non-zero balance of a token.
+		pub HolderCount get(fn holder_count): map hasher(blake2_128_concat) u32 => u32;
+		pub Freezed get(fn freezed): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => bool;
+		/// Part of an account's balance that cannot leave it. May exceed the balance.
+		pub FrozenBalance get(fn frozen_balance): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => BalanceOf<T>;
 		pub Supply get(fn supply): map hasher(blake2_128_concat) u32 => BalanceOf<T>;
 		pub Paused get(fn paused): map hasher(blake2_128_concat) u32 => bool;
-		pub Allowance get(fn allowance): map hasher(blake2_128_concat) (u32, T::AccountId, T::AccountId) => BalanceOf<T>;
-		pub Owner get(fn owner): map hasher(blake2_128_concat) u32 => T::AccountId;
+		/// What a spender may spend of an account's balance, by `(account, spender)`.
+		pub Allowance get(fn allowance): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) (T::AccountId, T::AccountId) => BalanceOf<T>;
+
+		/// Vesting schedule on an account's balance of a token.
+		pub Vesting get(fn vesting): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => Option<VestingInfoOf<T>>;
+
+		/// Account a token's owner offered ownership to, until it accepts.
+		pub PendingOwner get(fn pending_owner): map hasher(blake2_128_concat) u32 => Option<T::AccountId>;
+		/// Tokens only whitelisted accounts may receive.
+		pub Restricted get(fn restricted): map hasher(blake2_128_concat) u32 => bool;
+		pub Whitelist get(fn whitelisted): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => bool;
+		/// Lowest KYC level an account needs to receive a token, zero for none.
+		pub RequiredKyc get(fn required_kyc): map hasher(blake2_128_concat) u32 => u8;
+		pub Roles get(fn has_role): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) (TokenRole, T::AccountId) => bool;
+
+		pub SnapshotCount get(fn snapshot_count): SnapshotIndex;
+		pub Snapshots get(fn snapshots): map hasher(blake2_128_concat) SnapshotIndex => Option<SnapshotInfoOf<T>>;
+		/// Newest snapshot of each token.
+		pub LatestSnapshot get(fn latest_snapshot): map hasher(blake2_128_concat) u32 => Option<SnapshotIndex>;
+		/// Balances of a token an account held at its snapshots, as `(snapshot, balance)` in
+		/// snapshot order. Each is recorded when the balance first changes after the snapshot,
+		/// and also holds for the earlier snapshots since the previous entry.
+		pub Checkpoints get(fn checkpoints): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => Vec<(SnapshotIndex, BalanceOf<T>)>;
+		/// Snapshot of the last entry in `Checkpoints`, so transfers need not read them all.
+		pub LastCheckpoint get(fn last_checkpoint): 
+			double_map hasher(blake2_128_concat) u32, hasher(blake2_128_concat) T::AccountId => Option<SnapshotIndex>;
+		/// Accounts whose balances a snapshot leaves out.
+		pub ExcludedFromSnapshot get(fn excluded_from_snapshot): 
+			double_map hasher(blake2_128_concat) SnapshotIndex, hasher(blake2_128_concat) T::AccountId => bool;
+
+		pub DistributionCount get(fn distribution_count): DistributionIndex;
+		/// Distributions that can still be claimed from.
+		pub Distributions get(fn distribution): 
+			map hasher(blake2_128_concat) DistributionIndex => Option<DistributionInfoOf<T>>;
+		/// Distributions by the block they expire at.
+		pub DistributionQueue get(fn distribution_queue): 
+			double_map hasher(twox_64_concat) T::BlockNumber, hasher(twox_64_concat) DistributionIndex => ();
+		pub Claimed get(fn claimed): 
+			double_map hasher(blake2_128_concat) DistributionIndex, hasher(blake2_128_concat) T::AccountId => bool;
+		/// Expired distributions whose `Claimed` records are still being removed, oldest first.
+		pub ClaimsToClear get(fn claims_to_clear): Vec<DistributionIndex>;
 	}
 }
 
@@ -75,11 +294,18 @@ decl_event!(
 	where
 		AccountId = <T as system::Trait>::AccountId,
 		Balance = BalanceOf<T>,
+		BlockNumber = <T as system::Trait>::BlockNumber,
 	{
 		/// A token was created by user. \[token_id, owner_id\]
 		Created(u32, AccountId),
+		/// Token destroyed and its deposit released. \[token, owner\]
+		Destroyed(u32, AccountId),
+		/// Symbol reserved for an issuer, or released if `None`. \[symbol, issuer\]
+		SymbolReservationSet(Vec<u8>, Option<AccountId>),
 		/// Token burned. \[token, sender, amount\]
 		Burn(u32, AccountId, Balance),
+		/// Token burned to be redeemed off-chain. \[token, holder, amount, reference\]
+		Redeemed(u32, AccountId, Balance, Vec<u8>),
 		/// Token minted. \[token, receiver, amount\]
 		Mint(u32, AccountId, Balance),
 		/// Token edited. \[token\]
@@ -88,14 +314,46 @@ decl_event!(
 		Freeze(u32, AccountId),
 		/// Token thawed. \[token, user\]
 		Thaw(u32, AccountId),				
+		/// Part of a balance frozen. \[token, user, amount\]
+		BalanceFrozen(u32, AccountId, Balance),
+		/// Part of a balance thawed. \[token, user, amount\]
+		BalanceThawed(u32, AccountId, Balance),
+		/// Amount of a balance still vesting changed. \[token, user, unvested\]
+		VestingUpdated(u32, AccountId, Balance),
+		/// Balance fully vested. \[token, user\]
+		VestingCompleted(u32, AccountId),
 		/// Token transferred. \[token, sender, receiver, amount\]
 		Transfer(u32, AccountId, AccountId, Balance),	
-		/// Token transferred. \[token, user, spender, amount\]
+		/// Allowance spent. \[token, user, spender, amount\]
 		Spend(u32, AccountId, AccountId, Balance),				
 		/// Token approved. \[token, user, spender amount\]
 		Allowance(u32, AccountId, AccountId, Balance),
 		/// Token paused/unpaused. \[token, status\]
 		TokenPaused(u32, bool),
+		/// Token ownership offered. \[token, owner, new_owner\]
+		OwnershipOffered(u32, AccountId, AccountId),
+		/// Token ownership accepted. \[token, old_owner, new_owner\]
+		OwnershipTransferred(u32, AccountId, AccountId),
+		/// Token restricted to whitelisted holders or opened up. \[token, restricted\]
+		RestrictionSet(u32, bool),
+		/// Account added to a token's whitelist. \[token, account\]
+		Whitelisted(u32, AccountId),
+		/// Account removed from a token's whitelist. \[token, account\]
+		Unwhitelisted(u32, AccountId),
+		/// KYC level required to receive a token changed. \[token, level\]
+		RequiredKycSet(u32, u8),
+		/// Balances of a token recorded. \[token, snapshot, block\]
+		SnapshotTaken(u32, SnapshotIndex, BlockNumber),
+		/// Funds set aside for the holders of a token. \[distribution, token, snapshot, amount\]
+		Distributed(DistributionIndex, u32, SnapshotIndex, Balance),
+		/// Share of a distribution paid out. \[distribution, holder, amount\]
+		Claimed(DistributionIndex, AccountId, Balance),
+		/// Distribution expired and its unclaimed funds returned. \[distribution, amount\]
+		DistributionExpired(DistributionIndex, Balance),
+		/// Token role granted. \[token, role, account\]
+		RoleGranted(u32, TokenRole, AccountId),
+		/// Token role revoked. \[token, role, account\]
+		RoleRevoked(u32, TokenRole, AccountId),
 	}
 );
 
@@ -106,7 +364,47 @@ decl_error! {
 		InsufficientAllowance,
 		InsufficientBalance,
 		TokenPaused,
-		AccountFreezed
+		AccountFreezed,
+		TokenNotFound,
+		MissingRole,
+		NotPendingOwner,
+		ReferenceTooLong,
+		RecipientFreezed,
+		BalanceFrozen,
+		/// Token is restricted and the recipient is not on its whitelist
+		NotWhitelisted,
+		/// Recipient's KYC level is below the one the token requires
+		InsufficientKyc,
+		/// Distribution does not exist or has expired
+		DistributionNotFound,
+		/// Distributions must last at least one block
+		InvalidExpiry,
+		/// Token has no supply to distribute against
+		NoHolders,
+		/// Share of this distribution already paid out
+		AlreadyClaimed,
+		/// Account held none of the token at the distribution's snapshot
+		NothingToClaim,
+		/// Part of the balance is still vesting
+		BalanceVesting,
+		/// Account has no vesting schedule for the token
+		NotVesting,
+		/// Account already has a vesting schedule for the token
+		ExistingVestingSchedule,
+		/// Vesting schedule locks nothing or never unlocks
+		InvalidSchedule,
+		/// Vested transfer is below `MinVestedShare` of the token's supply
+		VestedAmountTooLow,
+		/// Another token already uses the symbol
+		SymbolTaken,
+		/// Symbol is reserved for another issuer
+		SymbolReserved,
+		/// Token still has supply, so cannot be destroyed
+		SupplyNotZero,
+		/// Minting would overflow a balance or the supply
+		Overflow,
+		/// Account is one of the `EscrowAccounts`, which cannot be frozen
+		EscrowAccount
 	}
 }
 
@@ -116,31 +414,52 @@ decl_module! {
 
 		type Error = Error<T>;	
 
+		fn on_runtime_upgrade() -> Weight {
+			migrations::migrate::<T>()
+		}
+
+		fn on_initialize(now: T::BlockNumber) -> Weight {
+			// Expiry in `on_finalize` moves funds and queues claim records for removal.
+			let due = <DistributionQueue<T>>::iter_prefix(now).count() as Weight;
+			Self::clear_claims()
+				.saturating_add(T::DbWeight::get().reads_writes(1 + 5 * due, 1 + 5 * due))
+		}
+
+		fn on_finalize(now: T::BlockNumber) {
+			Self::expire_distributions(now);
+		}
+
+		/// Create a token owned by the caller, who pays its deposit and receives the
+		/// `initial_supply`. Ownership can be handed over with `transfer_ownership`.
 		#[weight = 10_000]
 		pub fn create(origin, 
-			owner:AccountIdOf<T>, 
 			name:Vec<u8>, 
 			symbol: Vec<u8>, 
 			initial_supply: BalanceOf<T>
 		) -> DispatchResult {			
 			let caller = ensure_signed(origin)?;
+			let key = Self::symbol_key(&symbol);
+			Self::ensure_symbol_available(&key, &caller)?;
 
 			let index = TokenCount::get();
+			Self::hold_deposit(index, &caller, Self::metadata_deposit(&name, &symbol))?;
 			TokenCount::put(index + 1);		
+			if T::UniqueSymbols::get() {
+				<Symbols>::insert(key, index);
+			}
 			
 			let created = <system::Module<T>>::block_number();
-			Self::deposit_event(RawEvent::Created(index, owner.clone()));
+			Self::deposit_event(RawEvent::Created(index, caller.clone()));
 
 			<Tokens<T>>::insert(index, TokenInfo {
 				name,
 				symbol,
-				owner,
+				owner: caller.clone(),
 				created
 			});			
 
-			<Balance<T>>::insert((index, &caller), initial_supply);
+			Self::set_balance(index, &caller, initial_supply);
 			<Supply<T>>::insert(index, initial_supply);
-			<Owner<T>>::insert(index, &caller);
 
 
 			Ok(())
@@ -153,18 +472,7 @@ decl_module! {
 			value: BalanceOf<T> 
 		) -> DispatchResult {
 			let from = ensure_signed(origin)?;
-
-			let from_balance = Self::balance((token, &from));
-			ensure!(from_balance > value, <Error<T>>::InsufficientBalance);
-			
-			let paused = Self::paused(token);	
-			ensure!(paused != true, <Error<T>>::TokenPaused);
-			
-			let freezed = Self::freezed((token, &from));
-			ensure!(freezed != true, <Error<T>>::AccountFreezed);
-
-			Self::transfer_(token, from, to, value);
-			Ok(())
+			Self::do_transfer(token, from, to, value)
 		}	
 
 		#[weight = 10_000]
@@ -174,21 +482,19 @@ decl_module! {
 			value: BalanceOf<T> 
 		) -> DispatchResult {
 			let caller = ensure_signed(origin)?;
+			Self::do_transfer_from(token, caller.clone(), user, caller, value)
+		}	
 
-			let user_balance = Self::balance((token, &user));
-			ensure!(user_balance >= value, <Error<T>>::InsufficientBalance);
-			
-			let caller_allowance = Self::allowance((token, &user, &caller));
-			ensure!(caller_allowance >= value, <Error<T>>::InsufficientAllowance);
-			
-			let paused = Self::paused(token);	
-			ensure!(paused != true, <Error<T>>::TokenPaused);
-			
-			let freezed = Self::freezed((token, &user));
-			ensure!(freezed != true, <Error<T>>::AccountFreezed);
-
-			Self::spend_(token, user, caller, value);
-			Ok(())
+		/// Move `value` of `owner`'s `token` to `to`, using up the allowance `owner` gave the caller.
+		#[weight = 10_000]
+		pub fn transfer_from(origin, 
+			token: u32, 
+			owner: T::AccountId, 
+			to: T::AccountId, 
+			value: BalanceOf<T> 
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::do_transfer_from(token, caller, owner, to, value)
 		}	
 		
 		#[weight = 10_000]
@@ -198,10 +504,25 @@ decl_module! {
 			symbol: Vec<u8>
 		) -> DispatchResult {
 			let caller = ensure_signed(origin)?;
-			let token_owner = Self::owner(token);
-			ensure!(caller == token_owner, <Error<T>>::NotTokenOwner);
+			Self::ensure_owner(token, &caller)?;
 
 			let token_data = <Tokens<T>>::get(token);
+			let old_key = Self::symbol_key(&token_data.symbol);
+			let key = Self::symbol_key(&symbol);
+			if key != old_key {
+				Self::ensure_symbol_available(&key, &caller)?;
+			}
+			// The deposit follows the new metadata size, and moves to the current owner.
+			Self::hold_deposit(token, &caller, Self::metadata_deposit(&name, &symbol))?;
+			if key != old_key {
+				if Self::token_by_symbol(&old_key) == Some(token) {
+					<Symbols>::remove(&old_key);
+				}
+				if T::UniqueSymbols::get() {
+					<Symbols>::insert(key, token);
+				}
+			}
+
 			let token_owner = token_data.owner;
 			let token_created = token_data.created;
 
@@ -223,18 +544,10 @@ decl_module! {
 			status: bool 
 		) -> DispatchResult {
 			let caller = ensure_signed(origin)?;
-			let token_owner = Self::owner(token);
-			ensure!(caller == token_owner, <Error<T>>::NotTokenOwner);
-
-			let token_boolean = Self::paused(token);
-			let new_status: bool;
-			if token_boolean {
-				new_status = true;
-			} else {	
-				new_status = false;			
-			}
-			<Paused>::insert(token, new_status);			
-			Self::deposit_event(RawEvent::TokenPaused(token, new_status));
+			Self::ensure_role(token, TokenRole::Pauser, &caller)?;
+
+			<Paused>::insert(token, status);			
+			Self::deposit_event(RawEvent::TokenPaused(token, status));
 			Ok(())
 		}	
 		
@@ -244,12 +557,13 @@ decl_module! {
 			value: BalanceOf<T> 
 		) -> DispatchResult {
 			let minter = ensure_signed(origin)?;
-			let token_owner = Self::owner(token);
-			ensure!(minter == token_owner, <Error<T>>::NotTokenOwner);	
-			let minter_balance = Self::balance((token, &minter));
-			let token_supply = Self::supply(token);
-			<Balance<T>>::insert((token, &minter), minter_balance + value);
-			<Supply<T>>::insert(token, token_supply + value);
+			Self::ensure_role(token, TokenRole::Minter, &minter)?;
+			Self::ensure_eligible(token, &minter)?;
+			T::TransferHook::on_transfer(token, None, &minter, value)?;
+			let minter_balance = Self::balance(token, &minter).checked_add(&value).ok_or(<Error<T>>::Overflow)?;
+			let token_supply = Self::supply(token).checked_add(&value).ok_or(<Error<T>>::Overflow)?;
+			Self::set_balance(token, &minter, minter_balance);
+			<Supply<T>>::insert(token, token_supply);
 	
 			Self::deposit_event(RawEvent::Mint(token, minter, value));					
 			Ok(())
@@ -261,47 +575,119 @@ decl_module! {
 			value: BalanceOf<T> 
 		) -> DispatchResult {
 			let burner = ensure_signed(origin)?;
-			let token_owner = Self::owner(token);
-			ensure!(burner == token_owner, <Error<T>>::NotTokenOwner);			
-			let burner_balance = Self::balance((token, &burner));
-			let token_supply = Self::supply(token);
-	
-			<Balance<T>>::insert((token, &burner), burner_balance - value);
-			<Supply<T>>::insert(token, token_supply - value);
+			Self::ensure_role(token, TokenRole::Burner, &burner)?;
+			Self::burn_(token, &burner, value)?;
 	
 			Self::deposit_event(RawEvent::Burn(token, burner, value));
 			Ok(())
 		}	
 
+		/// Burn `value` of the caller's own `token`.
+		#[weight = 10_000]
+		pub fn burn_self(origin, 
+			token: u32, 
+			value: BalanceOf<T> 
+		) -> DispatchResult {
+			let holder = ensure_signed(origin)?;
+			Self::burn_(token, &holder, value)?;
+
+			Self::deposit_event(RawEvent::Burn(token, holder, value));
+			Ok(())
+		}
+
+		/// Burn `value` of `from`'s `token`, using the allowance `from` gave the caller.
+		#[weight = 10_000]
+		pub fn burn_from(origin, 
+			token: u32, 
+			from: T::AccountId, 
+			value: BalanceOf<T> 
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			let allowance = Self::allowance(token, (&from, &caller));
+			ensure!(allowance >= value, <Error<T>>::InsufficientAllowance);
+
+			Self::burn_(token, &from, value)?;
+			<Allowance<T>>::insert(token, (&from, &caller), allowance - value);
+
+			Self::deposit_event(RawEvent::Burn(token, from, value));
+			Ok(())
+		}
+
+		/// Burn `value` of the caller's `token` to redeem it off-chain. `reference` identifies
+		/// the redemption to the issuer, e.g. a bank transfer reference.
+		#[weight = 10_000]
+		pub fn redeem(origin, 
+			token: u32, 
+			value: BalanceOf<T>, 
+			reference: Vec<u8>
+		) -> DispatchResult {
+			let holder = ensure_signed(origin)?;
+			ensure!(reference.len() <= MAX_REFERENCE_LENGTH, <Error<T>>::ReferenceTooLong);
+			Self::burn_(token, &holder, value)?;
+
+			Self::deposit_event(RawEvent::Redeemed(token, holder, value, reference));
+			Ok(())
+		}
+
+		/// Freeze `user`'s whole account of `token`, which then can neither send nor
+		/// receive it. `EscrowAccounts` cannot be frozen.
 		#[weight = 10_000]
 		pub fn freeze(origin, 
 			user: T::AccountId, 
-			token:u32, 
-			value: BalanceOf<T> 
+			token: u32
 		) -> DispatchResult {
 			let caller = ensure_signed(origin)?;
-			let token_owner = Self::owner(token);
-			ensure!(caller == token_owner, <Error<T>>::NotTokenOwner);	
+			Self::ensure_role(token, TokenRole::Freezer, &caller)?;
+			ensure!(!T::EscrowAccounts::get().contains(&user), <Error<T>>::EscrowAccount);
 
-			<Freezed<T>>::insert((token, &user), true);
-	
-			Self::deposit_event(RawEvent::Freeze(token, user));					
+			<Freezed<T>>::insert(token, &user, true);
+			Self::deposit_event(RawEvent::Freeze(token, user));
 			Ok(())
 		}	
 
+		/// Undo `freeze`.
 		#[weight = 10_000]
 		pub fn thaw(origin, 
 			user: T::AccountId, 
-			token:u32, 
+			token: u32
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_role(token, TokenRole::Freezer, &caller)?;
+
+			<Freezed<T>>::remove(token, &user);
+			Self::deposit_event(RawEvent::Thaw(token, user));
+			Ok(())
+		}		
+
+		/// Freeze `value` of `user`'s balance of `token`. The rest of the balance stays
+		/// spendable. `EscrowAccounts` cannot be frozen.
+		#[weight = 10_000]
+		pub fn freeze_balance(origin, 
+			user: T::AccountId, 
+			token: u32, 
 			value: BalanceOf<T> 
 		) -> DispatchResult {
 			let caller = ensure_signed(origin)?;
-			let token_owner = Self::owner(token);
-			ensure!(caller == token_owner, <Error<T>>::NotTokenOwner);	
+			Self::ensure_role(token, TokenRole::Freezer, &caller)?;
+			ensure!(!T::EscrowAccounts::get().contains(&user), <Error<T>>::EscrowAccount);
 
-			<Freezed<T>>::insert((token, &user), false);
-	
-			Self::deposit_event(RawEvent::Thaw(token, user));					
+			<FrozenBalance<T>>::mutate(token, &user, |frozen| *frozen = frozen.saturating_add(value));
+			Self::deposit_event(RawEvent::BalanceFrozen(token, user, value));
+			Ok(())
+		}	
+
+		/// Undo `freeze_balance` for `value`.
+		#[weight = 10_000]
+		pub fn thaw_balance(origin, 
+			user: T::AccountId, 
+			token: u32, 
+			value: BalanceOf<T> 
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_role(token, TokenRole::Freezer, &caller)?;
+
+			<FrozenBalance<T>>::mutate(token, &user, |frozen| *frozen = frozen.saturating_sub(value));
+			Self::deposit_event(RawEvent::BalanceThawed(token, user, value));
 			Ok(())
 		}		
 
@@ -312,47 +698,692 @@ decl_module! {
 			value: BalanceOf<T> 
 		) -> DispatchResult {
 			let user = ensure_signed(origin)?;		
-			<Allowance<T>>::insert((token, &user, &spender), value);
-			Self::deposit_event(RawEvent::Allowance(token, user, spender, value));
-			Ok(())
+			Self::do_allow(token, user, spender, value)
 		}			
 
+		#[weight = 10_000]
+		pub fn increase_allowance(origin, 
+			token: u32, 
+			spender: T::AccountId, 
+			value: BalanceOf<T> 
+		) -> DispatchResult {
+			let user = ensure_signed(origin)?;
+			let allowance = Self::allowance(token, (&user, &spender)).saturating_add(value);
+			Self::do_allow(token, user, spender, allowance)
+		}
+
+		#[weight = 10_000]
+		pub fn decrease_allowance(origin, 
+			token: u32, 
+			spender: T::AccountId, 
+			value: BalanceOf<T> 
+		) -> DispatchResult {
+			let user = ensure_signed(origin)?;
+			let allowance = Self::allowance(token, (&user, &spender));
+			ensure!(allowance >= value, <Error<T>>::InsufficientAllowance);
+			Self::do_allow(token, user, spender, allowance - value)
+		}
+
+		/// Remove `token` once its whole supply is burned, releasing its deposit and symbol
+		/// and clearing its roles, whitelist, allowances and freezes.
+		#[weight = 10_000]
+		pub fn destroy(origin, 
+			token: u32
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_owner(token, &caller)?;
+			ensure!(Self::supply(token).is_zero(), <Error<T>>::SupplyNotZero);
+
+			let info = <Tokens<T>>::take(token);
+			let key = Self::symbol_key(&info.symbol);
+			if Self::token_by_symbol(&key) == Some(token) {
+				<Symbols>::remove(&key);
+			}
+			if let Some((depositor, deposit)) = <Deposit<T>>::take(token) {
+				T::Currency::unreserve(&depositor, deposit);
+			}
+			<Supply<T>>::remove(token);
+			<HolderCount>::remove(token);
+			<Paused>::remove(token);
+			<Restricted>::remove(token);
+			<RequiredKyc>::remove(token);
+			<PendingOwner<T>>::remove(token);
+			<Vesting<T>>::remove_prefix(token);
+			<Roles<T>>::remove_prefix(token);
+			<Whitelist<T>>::remove_prefix(token);
+			<Allowance<T>>::remove_prefix(token);
+			<Freezed<T>>::remove_prefix(token);
+			<FrozenBalance<T>>::remove_prefix(token);
+
+			Self::deposit_event(RawEvent::Destroyed(token, caller));
+			Ok(())
+		}
+
+		/// Reserve `symbol` so that only `issuer` can create or rename a token to it, or
+		/// release it with `None`. Tokens already using it keep it.
+		#[weight = 10_000]
+		pub fn reserve_symbol(origin, 
+			symbol: Vec<u8>, 
+			issuer: Option<T::AccountId>
+		) -> DispatchResult {
+			T::RegistryOrigin::ensure_origin(origin)?;
+			let key = Self::symbol_key(&symbol);
+			match &issuer {
+				Some(issuer) => <ReservedSymbols<T>>::insert(&key, issuer),
+				None => <ReservedSymbols<T>>::remove(&key),
+			}
+			Self::deposit_event(RawEvent::SymbolReservationSet(key, issuer));
+			Ok(())
+		}
+
+		/// Unlock whatever of the caller's `token` has vested, removing the schedule once
+		/// nothing is left locked.
+		#[weight = 10_000]
+		pub fn vest(origin, 
+			token: u32
+		) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let schedule = Self::vesting(token, &who).ok_or(<Error<T>>::NotVesting)?;
+			let locked = schedule.locked_at(<system::Module<T>>::block_number());
+			if locked.is_zero() {
+				<Vesting<T>>::remove(token, &who);
+				Self::deposit_event(RawEvent::VestingCompleted(token, who));
+			} else {
+				Self::deposit_event(RawEvent::VestingUpdated(token, who, locked));
+			}
+			Ok(())
+		}
+
+		/// Send `schedule.locked` of `token` to `target`, locked under `schedule`. `target` may
+		/// only have one schedule per token, and it locks at least `MinVestedShare` of the
+		/// token's supply.
+		#[weight = 10_000]
+		pub fn vested_transfer(origin, 
+			token: u32, 
+			target: T::AccountId,
+			schedule: VestingInfoOf<T>
+		) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
+			ensure!(!schedule.locked.is_zero() && !schedule.per_block.is_zero(), <Error<T>>::InvalidSchedule);
+			ensure!(schedule.locked >= T::MinVestedShare::get() * Self::supply(token), <Error<T>>::VestedAmountTooLow);
+			ensure!(!<Vesting<T>>::contains_key(token, &target), <Error<T>>::ExistingVestingSchedule);
+			ensure!(!Self::paused(token), <Error<T>>::TokenPaused);
+
+			Self::transfer_(token, sender, target.clone(), schedule.locked)?;
+			<Vesting<T>>::insert(token, &target, schedule);
+			let locked = schedule.locked_at(<system::Module<T>>::block_number());
+			Self::deposit_event(RawEvent::VestingUpdated(token, target, locked));
+			Ok(())
+		}
+
+		/// Restrict `token` to whitelisted holders, or lift the restriction. Balances already
+		/// held by accounts off the whitelist stay, but they cannot receive more.
+		#[weight = 10_000]
+		pub fn set_restricted(origin, 
+			token: u32, 
+			restricted: bool
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_owner(token, &caller)?;
+
+			<Restricted>::insert(token, restricted);
+			Self::deposit_event(RawEvent::RestrictionSet(token, restricted));
+			Ok(())
+		}
+
+		#[weight = 10_000]
+		pub fn whitelist(origin, 
+			token: u32, 
+			who: T::AccountId
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_role(token, TokenRole::Compliance, &caller)?;
+
+			<Whitelist<T>>::insert(token, &who, true);
+			Self::deposit_event(RawEvent::Whitelisted(token, who));
+			Ok(())
+		}
+
+		#[weight = 10_000]
+		pub fn unwhitelist(origin, 
+			token: u32, 
+			who: T::AccountId
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_role(token, TokenRole::Compliance, &caller)?;
+
+			<Whitelist<T>>::remove(token, &who);
+			Self::deposit_event(RawEvent::Unwhitelisted(token, who));
+			Ok(())
+		}
+
+		/// Require accounts to hold at least KYC `level` to receive `token`, zero to lift it.
+		/// Like the whitelist, it does not touch balances already held.
+		#[weight = 10_000]
+		pub fn set_required_kyc(origin, 
+			token: u32, 
+			level: u8
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_role(token, TokenRole::Compliance, &caller)?;
+
+			<RequiredKyc>::insert(token, level);
+			Self::deposit_event(RawEvent::RequiredKycSet(token, level));
+			Ok(())
+		}
+
+		/// Record every holder's balance of `token` at the current block, for dividends or
+		/// votes to be worked out from later with `balance_at`. Balances are only copied
+		/// as they change afterwards, so the cost does not grow with the holders.
+		#[weight = 10_000 + Module::<T>::snapshot_weight()]
+		pub fn snapshot(origin, 
+			token: u32
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_owner(token, &caller)?;
+
+			Self::do_snapshot(token);
+			Ok(())
+		}
+
+		/// Share `amount` of `asset`, or of the native currency if `None`, among the holders of
+		/// `token` in proportion to their balances now. The funds move to the distribution pool
+		/// at once; holders `claim` their share, and whatever is left after `expires_in` blocks
+		/// goes back. Takes a snapshot of `token`.
+		#[weight = 10_000 + Module::<T>::snapshot_weight()]
+		#[transactional]
+		pub fn distribute(origin, 
+			token: u32, 
+			asset: Option<u32>,
+			amount: BalanceOf<T>,
+			expires_in: T::BlockNumber
+		) -> DispatchResult {
+			let issuer = ensure_signed(origin)?;
+			Self::ensure_owner(token, &issuer)?;
+			ensure!(!amount.is_zero(), <Error<T>>::InsufficientAmount);
+			ensure!(!expires_in.is_zero(), <Error<T>>::InvalidExpiry);
+
+			let pool = Self::distribution_pool();
+			match asset {
+				Some(asset) => {
+					ensure!(<Tokens<T>>::contains_key(asset), <Error<T>>::TokenNotFound);
+					ensure!(!Self::freezed(asset, &issuer), <Error<T>>::AccountFreezed);
+					Self::ensure_spendable(asset, &issuer, amount)?;
+					// The pool is not a holder the token's rules apply to, so skip `transfer_`.
+					Self::set_balance(asset, &issuer, Self::balance(asset, &issuer) - amount);
+					Self::set_balance(asset, &pool, Self::balance(asset, &pool) + amount);
+				},
+				None => T::Currency::transfer(&issuer, &pool, amount, AllowDeath)?,
+			}
+
+			let snapshot = Self::do_snapshot(token);
+			let supply = Self::snapshots(snapshot).map(|info| info.supply).unwrap_or_else(Zero::zero);
+			ensure!(!supply.is_zero(), <Error<T>>::NoHolders);
+			let index = Self::distribution_count();
+			let expires = <system::Module<T>>::block_number() + expires_in;
+			<Distributions<T>>::insert(index, DistributionInfo {
+				issuer,
+				snapshot,
+				asset,
+				amount,
+				remaining: amount,
+				expires,
+			});
+			<DistributionQueue<T>>::insert(expires, index, ());
+			<DistributionCount>::put(index + 1);
+
+			Self::deposit_event(RawEvent::Distributed(index, token, snapshot, amount));
+			Ok(())
+		}
+
+		/// Pay the caller its share of `distribution`.
+		#[weight = 10_000]
+		pub fn claim(origin, 
+			distribution: DistributionIndex
+		) -> DispatchResult {
+			let holder = ensure_signed(origin)?;
+			let mut info = Self::distribution(distribution).ok_or(<Error<T>>::DistributionNotFound)?;
+			ensure!(!Self::claimed(distribution, &holder), <Error<T>>::AlreadyClaimed);
+			let share = Self::share(&info, &holder);
+			ensure!(!share.is_zero(), <Error<T>>::NothingToClaim);
+
+			// Either pays the whole share or fails.
+			match info.asset {
+				Some(asset) => Self::transfer_(asset, Self::distribution_pool(), holder.clone(), share)?,
+				None => T::Currency::transfer(&Self::distribution_pool(), &holder, share, AllowDeath)?,
+			}
+
+			info.remaining = info.remaining.saturating_sub(share);
+			<Distributions<T>>::insert(distribution, info);
+			<Claimed<T>>::insert(distribution, &holder, true);
+			Self::deposit_event(RawEvent::Claimed(distribution, holder, share));
+			Ok(())
+		}
+
+		/// Offer ownership of `token` to `new_owner`, who takes it with `accept_ownership`.
+		/// Replaces any earlier offer.
+		#[weight = 10_000]
+		pub fn transfer_ownership(origin, 
+			token: u32, 
+			new_owner: T::AccountId
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_owner(token, &caller)?;
+
+			<PendingOwner<T>>::insert(token, &new_owner);
+			Self::deposit_event(RawEvent::OwnershipOffered(token, caller, new_owner));
+			Ok(())
+		}
+
+		#[weight = 10_000]
+		pub fn accept_ownership(origin, 
+			token: u32
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			ensure!(Self::pending_owner(token).as_ref() == Some(&caller), <Error<T>>::NotPendingOwner);
+
+			<PendingOwner<T>>::remove(token);
+			let old_owner = <Tokens<T>>::mutate(token, |info| sp_std::mem::replace(&mut info.owner, caller.clone()));
+			Self::deposit_event(RawEvent::OwnershipTransferred(token, old_owner, caller));
+			Ok(())
+		}
+
+		#[weight = 10_000]
+		pub fn grant_role(origin, 
+			token: u32, 
+			role: TokenRole, 
+			who: T::AccountId
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_owner(token, &caller)?;
+
+			<Roles<T>>::insert(token, (role, &who), true);
+			Self::deposit_event(RawEvent::RoleGranted(token, role, who));
+			Ok(())
+		}
+
+		#[weight = 10_000]
+		pub fn revoke_role(origin, 
+			token: u32, 
+			role: TokenRole, 
+			who: T::AccountId
+		) -> DispatchResult {
+			let caller = ensure_signed(origin)?;
+			Self::ensure_owner(token, &caller)?;
+
+			<Roles<T>>::remove(token, (role, &who));
+			Self::deposit_event(RawEvent::RoleRevoked(token, role, who));
+			Ok(())
+		}
+
 	
 	}
 }
 
 impl<T: Trait> Module<T> {
 
-	pub fn spend_(token: u32, user: AccountIdOf<T>, spender: AccountIdOf<T>, value: BalanceOf<T> ) -> () {
-		let user_balance = Self::balance((token, &user));
-		let spender_balance = Self::balance((token, &spender));
+	/// Current owner of `token`.
+	pub fn owner(token: u32) -> AccountIdOf<T> {
+		Self::tokens(token).owner
+	}
 
-		<Balance<T>>::insert((token, &user), user_balance - value);
-		<Balance<T>>::insert((token, &spender), spender_balance + value);
-		Self::deposit_event(RawEvent::Spend(token, user, spender, value));
-	
-	}	
+	fn ensure_owner(token: u32, who: &AccountIdOf<T>) -> DispatchResult {
+		ensure!(<Tokens<T>>::contains_key(token), <Error<T>>::TokenNotFound);
+		ensure!(*who == Self::owner(token), <Error<T>>::NotTokenOwner);
+		Ok(())
+	}
 
-	pub fn transfer_(token: u32, from: AccountIdOf<T>, to: AccountIdOf<T>, value: BalanceOf<T> ) -> () {
-		let from_balance = Self::balance((token, &from));
-		let to_balance = Self::balance((token, &to));		
+	/// Whether `who` may act as `role` for `token`, either holding it or owning the token.
+	fn ensure_role(token: u32, role: TokenRole, who: &AccountIdOf<T>) -> DispatchResult {
+		ensure!(<Tokens<T>>::contains_key(token), <Error<T>>::TokenNotFound);
+		ensure!(*who == Self::owner(token) || Self::has_role(token, (role, who)), <Error<T>>::MissingRole);
+		Ok(())
+	}
 
-		<Balance<T>>::insert((token, &from), from_balance - value);
-		<Balance<T>>::insert((token, &to), to_balance + value);
-		Self::deposit_event(RawEvent::Transfer(token, from, to, value));
+	/// Checked transfer of `value` of `token` from `from`, as done by the `transfer` call.
+	fn do_transfer(token: u32, from: AccountIdOf<T>, to: AccountIdOf<T>, value: BalanceOf<T> ) -> DispatchResult {
+		ensure!(!Self::paused(token), <Error<T>>::TokenPaused);
+		Self::transfer_(token, from, to, value)
+	}
+
+	/// `spender` moves `value` of `owner`'s `token` to `to`, using up its allowance.
+	pub fn do_transfer_from(token: u32, spender: AccountIdOf<T>, owner: AccountIdOf<T>, to: AccountIdOf<T>, value: BalanceOf<T> ) -> DispatchResult {
+		let allowance = Self::allowance(token, (&owner, &spender));
+		ensure!(allowance >= value, <Error<T>>::InsufficientAllowance);
+		ensure!(!Self::paused(token), <Error<T>>::TokenPaused);
+
+		Self::transfer_(token, owner.clone(), to, value)?;
+		<Allowance<T>>::insert(token, (&owner, &spender), allowance - value);
+		Self::deposit_event(RawEvent::Spend(token, owner, spender, value));
+		Ok(())
+	}
+
+	/// Checks every movement of `token` makes, whoever initiates it, the exchange included.
+	fn ensure_can_move(token: u32, from: &AccountIdOf<T>, to: &AccountIdOf<T>, value: BalanceOf<T> ) -> DispatchResult {
+		ensure!(!Self::freezed(token, from), <Error<T>>::AccountFreezed);
+		ensure!(!Self::freezed(token, to), <Error<T>>::RecipientFreezed);
+		Self::ensure_eligible(token, to)?;
+		Self::ensure_spendable(token, from, value)?;
+		T::TransferHook::on_transfer(token, Some(from), to, value)
+	}
+
+	/// Whitelist and KYC checks on an account receiving `token`. `EscrowAccounts` are
+	/// exempt from KYC, the holders whose tokens they keep being checked instead.
+	fn ensure_eligible(token: u32, who: &AccountIdOf<T>) -> DispatchResult {
+		ensure!(!Self::restricted(token) || Self::whitelisted(token, who), <Error<T>>::NotWhitelisted);
+		ensure!(
+			T::KycProvider::kyc_level(who) >= Self::required_kyc(token) || T::EscrowAccounts::get().contains(who),
+			<Error<T>>::InsufficientKyc
+		);
+		Ok(())
+	}
+
+	fn ensure_spendable(token: u32, who: &AccountIdOf<T>, value: BalanceOf<T> ) -> DispatchResult {
+		let balance = Self::balance(token, who);
+		ensure!(balance >= value, <Error<T>>::InsufficientBalance);
+		ensure!(balance.saturating_sub(Self::frozen_balance(token, who)) >= value, <Error<T>>::BalanceFrozen);
+		ensure!(Self::free_balance(token, who) >= value, <Error<T>>::BalanceVesting);
+		Ok(())
+	}
+
+	/// Part of `who`'s balance of `token` that is neither frozen nor still vesting.
+	pub fn free_balance(token: u32, who: &AccountIdOf<T>) -> BalanceOf<T> {
+		Self::balance(token, who)
+			.saturating_sub(Self::frozen_balance(token, who))
+			.saturating_sub(Self::vesting_balance(token, who))
+	}
 
+	/// Part of `who`'s balance of `token` still locked by its vesting schedule.
+	pub fn vesting_balance(token: u32, who: &AccountIdOf<T>) -> BalanceOf<T> {
+		Self::vesting(token, who)
+			.map(|schedule| schedule.locked_at(<system::Module<T>>::block_number()))
+			.unwrap_or_else(Zero::zero)
+	}
+
+	/// Whether `who` may be sent `token`, as far as freezes, the whitelist and KYC go.
+	pub fn can_receive(token: u32, who: &AccountIdOf<T>) -> bool {
+		!Self::freezed(token, who) && Self::ensure_eligible(token, who).is_ok()
+	}
+
+	/// Let `spender` spend up to `value` of `user`'s `token`, as done by the `allow` call.
+	fn do_allow(token: u32, user: AccountIdOf<T>, spender: AccountIdOf<T>, value: BalanceOf<T> ) -> DispatchResult {
+		<Allowance<T>>::insert(token, (&user, &spender), value);
+		Self::deposit_event(RawEvent::Allowance(token, user, spender, value));
+		Ok(())
+	}
+
+	/// Remove `value` of `who`'s `token` from circulation.
+	fn burn_(token: u32, who: &AccountIdOf<T>, value: BalanceOf<T>) -> DispatchResult {
+		ensure!(<Tokens<T>>::contains_key(token), <Error<T>>::TokenNotFound);
+		ensure!(!Self::paused(token), <Error<T>>::TokenPaused);
+		ensure!(!Self::freezed(token, who), <Error<T>>::AccountFreezed);
+		Self::ensure_spendable(token, who, value)?;
+
+		Self::set_balance(token, who, Self::balance(token, who) - value);
+		<Supply<T>>::mutate(token, |supply| *supply -= value);
+		Ok(())
+	}
+
+	/// Move `value` of `token`, enforcing freezes but not pauses. Used for exchange escrow.
+	pub fn transfer_(token: u32, from: AccountIdOf<T>, to: AccountIdOf<T>, value: BalanceOf<T> ) -> DispatchResult {
+		Self::ensure_can_move(token, &from, &to, value)?;
+
+		// Read `to` after writing `from`, so sending to oneself leaves the balance as it was.
+		Self::set_balance(token, &from, Self::balance(token, &from) - value);
+		Self::set_balance(token, &to, Self::balance(token, &to) + value);
+		Self::deposit_event(RawEvent::Transfer(token, from, to, value));
+		Ok(())
 	}
 
 	pub fn get_allowance(token: u32, user: AccountIdOf<T>, spender: AccountIdOf<T> ) -> BalanceOf<T> {
-		Self::allowance((token, user, spender))
+		Self::allowance(token, (user, spender))
 	}		
 
 	pub fn get_balance(token: u32, who: AccountIdOf<T> ) -> BalanceOf<T> {
-		Self::balance((token, who))
+		Self::balance(token, who)
+	}
+
+	/// Write `who`'s balance of `token`, keeping `HolderCount` in step. Zero balances are
+	/// removed rather than stored.
+	fn set_balance(token: u32, who: &AccountIdOf<T>, value: BalanceOf<T>) {
+		Self::checkpoint(token, who);
+		let held = <Balance<T>>::contains_key(token, who);
+		if value.is_zero() {
+			<Balance<T>>::remove(token, who);
+			if held {
+				<HolderCount>::mutate(token, |count| *count = count.saturating_sub(1));
+			}
+		} else {
+			<Balance<T>>::insert(token, who, value);
+			if !held {
+				<HolderCount>::mutate(token, |count| *count = count.saturating_add(1));
+			}
+		}
+	}
+
+	/// Registry key of `symbol`; tickers differing only in case collide.
+	fn symbol_key(symbol: &[u8]) -> Vec<u8> {
+		symbol.to_ascii_uppercase()
+	}
+
+	fn ensure_symbol_available(key: &[u8], issuer: &AccountIdOf<T>) -> DispatchResult {
+		if T::UniqueSymbols::get() {
+			ensure!(!<Symbols>::contains_key(key), <Error<T>>::SymbolTaken);
+		}
+		if let Some(reserved_for) = Self::symbol_reservation(key) {
+			ensure!(reserved_for == *issuer, <Error<T>>::SymbolReserved);
+		}
+		Ok(())
+	}
+
+	/// Deposit a token with this `name` and `symbol` is held for.
+	fn metadata_deposit(name: &[u8], symbol: &[u8]) -> BalanceOf<T> {
+		let bytes = (name.len() + symbol.len()) as u32;
+		T::TokenDeposit::get().saturating_add(T::MetadataDepositPerByte::get().saturating_mul(bytes.into()))
+	}
+
+	/// Hold `deposit` for `token` from `who`, releasing whatever was held for it before.
+	fn hold_deposit(token: u32, who: &AccountIdOf<T>, deposit: BalanceOf<T>) -> DispatchResult {
+		match <Deposit<T>>::get(token) {
+			Some((depositor, held)) if depositor == *who => {
+				if deposit > held {
+					T::Currency::reserve(who, deposit - held)?;
+				} else {
+					T::Currency::unreserve(who, held - deposit);
+				}
+			},
+			previous => {
+				T::Currency::reserve(who, deposit)?;
+				if let Some((depositor, held)) = previous {
+					T::Currency::unreserve(&depositor, held);
+				}
+			},
+		}
+		<Deposit<T>>::insert(token, (who.clone(), deposit));
+		Ok(())
+	}
+
+	/// Account the tokens of open distributions are held in.
+	pub fn distribution_pool() -> AccountIdOf<T> {
+		DISTRIBUTION_POOL.into_account()
+	}
+
+	/// What `who` is owed from `info`, ignoring whether it already claimed.
+	fn share(info: &DistributionInfoOf<T>, who: &AccountIdOf<T>) -> BalanceOf<T> {
+		let supply = match Self::snapshots(info.snapshot) {
+			Some(snapshot) => snapshot.supply,
+			None => return Zero::zero(),
+		};
+		multiply_by_rational(
+			info.amount.saturated_into(),
+			Self::balance_at(info.snapshot, who).saturated_into(),
+			supply.saturated_into(),
+		).map(|share| share.saturated_into()).unwrap_or_else(|_| Zero::zero())
+	}
+
+	/// What `who` can still claim from `distribution`.
+	pub fn pending_claim(distribution: DistributionIndex, who: &AccountIdOf<T>) -> BalanceOf<T> {
+		match Self::distribution(distribution) {
+			Some(info) if !Self::claimed(distribution, who) => Self::share(&info, who),
+			_ => Zero::zero(),
+		}
+	}
+
+	/// Every open distribution `who` has something to claim from, with the amount.
+	pub fn pending_claims(who: &AccountIdOf<T>) -> Vec<(DistributionIndex, BalanceOf<T>)> {
+		<Distributions<T>>::iter()
+			.filter(|(index, _)| !Self::claimed(index, who))
+			.map(|(index, info)| (index, Self::share(&info, who)))
+			.filter(|(_, share)| !share.is_zero())
+			.collect()
+	}
+
+	/// Close every distribution expiring at `now` and return what is left to its issuer.
+	fn expire_distributions(now: T::BlockNumber) {
+		let mut due: Vec<DistributionIndex> = <DistributionQueue<T>>::iter_prefix(now)
+			.map(|(index, _)| index)
+			.collect();
+		due.sort();
+		<DistributionQueue<T>>::remove_prefix(now);
+
+		let pool = Self::distribution_pool();
+		for index in due {
+			let info = match <Distributions<T>>::take(index) {
+				Some(info) => info,
+				None => continue,
+			};
+			match info.asset {
+				Some(asset) => {
+					Self::set_balance(asset, &pool, Self::balance(asset, &pool).saturating_sub(info.remaining));
+					Self::set_balance(asset, &info.issuer, Self::balance(asset, &info.issuer) + info.remaining);
+				},
+				None => {
+					let refund = info.remaining.min(T::Currency::free_balance(&pool));
+					// Only fails for a refund below the existential deposit to a reaped
+					// issuer, which is left in the pool.
+					let _ = T::Currency::transfer(&pool, &info.issuer, refund, AllowDeath);
+				},
+			}
+			<ClaimsToClear>::append(index);
+			Self::deposit_event(RawEvent::DistributionExpired(index, info.remaining));
+		}
+	}
+
+	/// Remove up to `MaxClaimsCleared` claim records of expired distributions, oldest
+	/// first, and return the weight used.
+	fn clear_claims() -> Weight {
+		let mut pending = Self::claims_to_clear();
+		if pending.is_empty() {
+			return T::DbWeight::get().reads(1);
+		}
+
+		let mut budget = T::MaxClaimsCleared::get();
+		let mut reads: Weight = 1;
+		let mut writes: Weight = 1;
+		while let Some(&index) = pending.first() {
+			let claimants: Vec<AccountIdOf<T>> = <Claimed<T>>::iter_prefix(index)
+				.map(|(who, _)| who)
+				.take(budget as usize)
+				.collect();
+			for who in claimants.iter() {
+				<Claimed<T>>::remove(index, who);
+			}
+			reads += claimants.len() as Weight + 1;
+			writes += claimants.len() as Weight;
+
+			// With the budget spent there may be more left, so check again next block.
+			if claimants.len() as u32 == budget {
+				break;
+			}
+			budget -= claimants.len() as u32;
+			pending.remove(0);
+		}
+		<ClaimsToClear>::put(pending);
+
+		T::DbWeight::get().reads_writes(reads, writes)
+	}
+
+	/// Every account holding `token`, with its balance.
+	pub fn holders(token: u32) -> Vec<(AccountIdOf<T>, BalanceOf<T>)> {
+		<Balance<T>>::iter_prefix(token).collect()
+	}
+
+	/// Database weight of `do_snapshot`: a read and a write per excluded account, on top
+	/// of the snapshot's own bookkeeping.
+	pub fn snapshot_weight() -> Weight {
+		let excluded = T::SnapshotExcluded::get().len() as Weight + 1;
+		T::DbWeight::get().reads_writes(excluded + 3, excluded + 3)
+	}
+
+	/// Start a new snapshot of `token`, as the `snapshot` call does. Its supply and holders
+	/// are the token's less the `SnapshotExcluded` accounts and the distribution pool,
+	/// while balances are only recorded by `checkpoint` as they change.
+	pub fn do_snapshot(token: u32) -> SnapshotIndex {
+		let index = Self::snapshot_count();
+		let block = <system::Module<T>>::block_number();
+		let mut excluded = T::SnapshotExcluded::get();
+		excluded.push(Self::distribution_pool());
+		excluded.sort();
+		excluded.dedup();
+
+		let mut holders = Self::holder_count(token);
+		let mut supply = Self::supply(token);
+		for who in excluded {
+			let balance = Self::balance(token, &who);
+			if !balance.is_zero() {
+				holders = holders.saturating_sub(1);
+				supply = supply.saturating_sub(balance);
+			}
+			<ExcludedFromSnapshot<T>>::insert(index, who, true);
+		}
+		<Snapshots<T>>::insert(index, SnapshotInfo {
+			token,
+			block,
+			supply,
+			holders,
+		});
+		<LatestSnapshot>::insert(token, index);
+		<SnapshotCount>::put(index + 1);
+		Self::deposit_event(RawEvent::SnapshotTaken(token, index, block));
+		index
 	}
 	
+	/// Before `who`'s balance of `token` first changes after the token's latest snapshot,
+	/// record what it was then.
+	fn checkpoint(token: u32, who: &AccountIdOf<T>) {
+		let latest = match Self::latest_snapshot(token) {
+			Some(latest) => latest,
+			None => return,
+		};
+		if Self::last_checkpoint(token, who) != Some(latest) {
+			<Checkpoints<T>>::append(token, who, (latest, Self::balance(token, who)));
+			<LastCheckpoint<T>>::insert(token, who, latest);
+		}
+	}
+
+	/// Balance `who` held when `snapshot` was taken, or zero if the snapshot left it out.
+	pub fn balance_at(snapshot: SnapshotIndex, who: &AccountIdOf<T>) -> BalanceOf<T> {
+		let token = match Self::snapshots(snapshot) {
+			Some(info) => info.token,
+			None => return Zero::zero(),
+		};
+		if Self::excluded_from_snapshot(snapshot, who) {
+			return Zero::zero();
+		}
+		// The first checkpoint from `snapshot` on holds the balance then. Without one, the
+		// balance has not changed since.
+		let checkpoints = Self::checkpoints(token, who);
+		let first = match checkpoints.binary_search_by_key(&snapshot, |(index, _)| *index) {
+			Ok(first) | Err(first) => first,
+		};
+		match checkpoints.get(first) {
+			Some((_, balance)) => *balance,
+			None => Self::balance(token, who),
+		}
+	}
+
 	pub fn get_account_freezed(token: u32, who: AccountIdOf<T> ) -> bool {
-		Self::freezed((token, who))
+		Self::freezed(token, who)
 	}	
 
 
@@ -0,0 +1,311 @@
+//! Storage migrations of the token pallet.
+//!
+//! `migrate` is run from `on_runtime_upgrade` and brings storage from whatever
+//! `StorageVersion` it is at up to the current `Releases`, one version at a time.
+
+use super::*;
+use frame_support::{traits::Get, weights::Weight};
+
+/// Run every migration the stored version is behind on.
+pub fn migrate<T: Trait>() -> Weight {
+	let mut weight = T::DbWeight::get().reads(1);
+
+	if StorageVersion::get() == Releases::V0 {
+		weight = weight.saturating_add(v1::migrate::<T>());
+		StorageVersion::put(Releases::V1);
+	}
+	if StorageVersion::get() == Releases::V1 {
+		weight = weight.saturating_add(v2::migrate::<T>());
+		StorageVersion::put(Releases::V2);
+	}
+	if StorageVersion::get() == Releases::V2 {
+		weight = weight.saturating_add(v3::migrate::<T>());
+		StorageVersion::put(Releases::V3);
+	}
+	if StorageVersion::get() == Releases::V3 {
+		weight = weight.saturating_add(v4::migrate::<T>());
+		StorageVersion::put(Releases::V4);
+	}
+
+	weight
+}
+
+/// V0 to V1: the owner of each token is kept only in `TokenInfo`.
+///
+/// V0 checked permissions against a separate `Owner` map, which `create` set to the
+/// caller rather than to `TokenInfo.owner`. That map is what V0 enforced, so it wins.
+pub mod v1 {
+	use super::*;
+	use frame_support::storage::migration::StorageIterator;
+
+	const MODULE: &[u8] = b"TokenStore";
+
+	/// Remove the V0 `Owner` map and return its entries.
+	fn take_owners<T: Trait>() -> Vec<(u32, AccountIdOf<T>)> {
+		StorageIterator::<AccountIdOf<T>>::new(MODULE, b"Owner")
+			.drain()
+			// Keys are `blake2_128_concat`: a 16 byte hash followed by the key itself.
+			.filter_map(|(key, owner)| u32::decode(&mut &key[16..]).ok().map(|token| (token, owner)))
+			.collect()
+	}
+
+	pub fn migrate<T: Trait>() -> Weight {
+		let owners = take_owners::<T>();
+		let count = owners.len() as Weight;
+
+		for (token, owner) in owners {
+			<Tokens<T>>::mutate(token, |info| info.owner = owner);
+		}
+
+		T::DbWeight::get().reads_writes(count, count * 2)
+	}
+
+	/// The V0 owner of every token, to compare against `post_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn pre_migrate<T: Trait>() -> Result<Vec<(u32, AccountIdOf<T>)>, &'static str> {
+		if StorageVersion::get() != Releases::V0 {
+			return Err("token storage is not at V0");
+		}
+
+		Ok(StorageIterator::<AccountIdOf<T>>::new(MODULE, b"Owner")
+			.filter_map(|(key, owner)| u32::decode(&mut &key[16..]).ok().map(|token| (token, owner)))
+			.collect())
+	}
+
+	/// Check every token kept the `owners` counted by `pre_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn post_migrate<T: Trait>(owners: Vec<(u32, AccountIdOf<T>)>) -> Result<(), &'static str> {
+		if StorageVersion::get() == Releases::V0 {
+			return Err("token storage was not bumped to V1");
+		}
+		if StorageIterator::<AccountIdOf<T>>::new(MODULE, b"Owner").next().is_some() {
+			return Err("owner map was not removed");
+		}
+		for (token, owner) in owners {
+			if Module::<T>::owner(token) != owner {
+				return Err("token owner changed");
+			}
+		}
+		Ok(())
+	}
+}
+
+/// V1 to V2: `Balance` becomes a double map keyed by token, then account, and
+/// `HolderCount` is filled in. Zero balances V1 left behind are dropped.
+pub mod v2 {
+	use super::*;
+	use frame_support::storage::migration::StorageIterator;
+
+	const MODULE: &[u8] = b"TokenStore";
+
+	/// Remove every V1 `Balance` entry and return them.
+	fn take_balances<T: Trait>() -> Vec<(u32, AccountIdOf<T>, BalanceOf<T>)> {
+		StorageIterator::<BalanceOf<T>>::new(MODULE, b"Balance")
+			.drain()
+			.filter_map(|(key, balance)| {
+				<(u32, AccountIdOf<T>)>::decode(&mut &key[16..]).ok().map(|(token, who)| (token, who, balance))
+			})
+			.collect()
+	}
+
+	pub fn migrate<T: Trait>() -> Weight {
+		// Both layouts share the `Balance` prefix, so every old entry is taken before any
+		// new one is written.
+		let balances = take_balances::<T>();
+		let count = balances.len() as Weight;
+
+		for (token, who, balance) in balances {
+			Module::<T>::set_balance(token, &who, balance);
+		}
+
+		T::DbWeight::get().reads_writes(count * 2, count * 3)
+	}
+
+	/// The non-zero V1 balances, to compare against `post_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn pre_migrate<T: Trait>() -> Result<Vec<(u32, AccountIdOf<T>, BalanceOf<T>)>, &'static str> {
+		if StorageVersion::get() != Releases::V1 {
+			return Err("token storage is not at V1");
+		}
+
+		Ok(StorageIterator::<BalanceOf<T>>::new(MODULE, b"Balance")
+			.filter_map(|(key, balance)| {
+				<(u32, AccountIdOf<T>)>::decode(&mut &key[16..]).ok().map(|(token, who)| (token, who, balance))
+			})
+			.filter(|(_, _, balance)| !balance.is_zero())
+			.collect())
+	}
+
+	/// Check every balance counted by `pre_migrate` survived, and holders were counted.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn post_migrate<T: Trait>(balances: Vec<(u32, AccountIdOf<T>, BalanceOf<T>)>) -> Result<(), &'static str> {
+		if StorageVersion::get() == Releases::V0 || StorageVersion::get() == Releases::V1 {
+			return Err("token storage was not bumped to V2");
+		}
+		let mut holders = sp_std::collections::btree_map::BTreeMap::<u32, u32>::new();
+		for (token, who, balance) in balances {
+			if Module::<T>::balance(token, &who) != balance {
+				return Err("token balance changed");
+			}
+			*holders.entry(token).or_default() += 1;
+		}
+		for (token, count) in holders {
+			if Module::<T>::holder_count(token) != count {
+				return Err("holder count does not match balances");
+			}
+		}
+		Ok(())
+	}
+}
+
+/// V2 to V3: existing symbols are registered in `Symbols`. Where tokens share a
+/// symbol the oldest keeps it, and the others stay unregistered until renamed.
+/// Tokens created before V3 hold no deposit. Nothing is registered without
+/// `UniqueSymbols`.
+pub mod v3 {
+	use super::*;
+
+	pub fn migrate<T: Trait>() -> Weight {
+		if !T::UniqueSymbols::get() {
+			return 0;
+		}
+		let mut tokens: Vec<(TokenIndex, Vec<u8>)> = <Tokens<T>>::iter()
+			.map(|(index, info)| (index, info.symbol))
+			.collect();
+		tokens.sort();
+		let count = tokens.len() as Weight;
+
+		for (index, symbol) in tokens {
+			let key = Module::<T>::symbol_key(&symbol);
+			if !<Symbols>::contains_key(&key) {
+				<Symbols>::insert(key, index);
+			}
+		}
+
+		T::DbWeight::get().reads_writes(count * 2, count)
+	}
+
+	/// Number of tokens, to compare against `post_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn pre_migrate<T: Trait>() -> Result<u32, &'static str> {
+		if StorageVersion::get() != Releases::V2 {
+			return Err("token storage is not at V2");
+		}
+		Ok(<Tokens<T>>::iter().count() as u32)
+	}
+
+	/// Check every symbol of the `tokens` counted by `pre_migrate` is registered.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn post_migrate<T: Trait>(tokens: u32) -> Result<(), &'static str> {
+		if StorageVersion::get() == Releases::V0 || StorageVersion::get() == Releases::V1 ||
+			StorageVersion::get() == Releases::V2 {
+			return Err("token storage was not bumped to V3");
+		}
+		let mut counted = 0u32;
+		for (_, info) in <Tokens<T>>::iter() {
+			if T::UniqueSymbols::get() && Module::<T>::token_by_symbol(Module::<T>::symbol_key(&info.symbol)).is_none() {
+				return Err("token symbol not registered");
+			}
+			counted += 1;
+		}
+		if counted != tokens {
+			return Err("token count changed");
+		}
+		Ok(())
+	}
+}
+
+/// V3 to V4: `Roles`, `Whitelist`, `Allowance`, `Freezed` and `FrozenBalance` move from
+/// maps keyed by tuples to double maps keyed by token first, so `destroy` can clear a
+/// token's entries. Entries holding the default value are dropped.
+pub mod v4 {
+	use super::*;
+	use frame_support::storage::migration::StorageIterator;
+
+	const MODULE: &[u8] = b"TokenStore";
+
+	/// Remove every V3 entry of `item` and return them with their decoded keys.
+	fn take<K: Decode, V: Decode>(item: &[u8]) -> Vec<(K, V)> {
+		StorageIterator::<V>::new(MODULE, item)
+			.drain()
+			// Keys are `blake2_128_concat`: a 16 byte hash followed by the key itself.
+			.filter_map(|(key, value)| K::decode(&mut &key[16..]).ok().map(|k| (k, value)))
+			.collect()
+	}
+
+	pub fn migrate<T: Trait>() -> Weight {
+		// Each layout shares its prefix with the old one, so every old entry of a map is
+		// taken before any new one is written.
+		let mut count: Weight = 0;
+
+		let roles = take::<(u32, TokenRole, AccountIdOf<T>), bool>(b"Roles");
+		count += roles.len() as Weight;
+		for ((token, role, who), granted) in roles {
+			if granted {
+				<Roles<T>>::insert(token, (role, who), true);
+			}
+		}
+		let whitelist = take::<(u32, AccountIdOf<T>), bool>(b"Whitelist");
+		count += whitelist.len() as Weight;
+		for ((token, who), listed) in whitelist {
+			if listed {
+				<Whitelist<T>>::insert(token, who, true);
+			}
+		}
+		let allowances = take::<(u32, AccountIdOf<T>, AccountIdOf<T>), BalanceOf<T>>(b"Allowance");
+		count += allowances.len() as Weight;
+		for ((token, owner, spender), allowance) in allowances {
+			if !allowance.is_zero() {
+				<Allowance<T>>::insert(token, (owner, spender), allowance);
+			}
+		}
+		let freezes = take::<(u32, AccountIdOf<T>), bool>(b"Freezed");
+		count += freezes.len() as Weight;
+		for ((token, who), frozen) in freezes {
+			if frozen {
+				<Freezed<T>>::insert(token, who, true);
+			}
+		}
+		let frozen_balances = take::<(u32, AccountIdOf<T>), BalanceOf<T>>(b"FrozenBalance");
+		count += frozen_balances.len() as Weight;
+		for ((token, who), frozen) in frozen_balances {
+			if !frozen.is_zero() {
+				<FrozenBalance<T>>::insert(token, who, frozen);
+			}
+		}
+
+		T::DbWeight::get().reads_writes(count, count * 2)
+	}
+
+	/// Number of V3 entries holding something other than the default, to compare
+	/// against `post_migrate`.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn pre_migrate<T: Trait>() -> Result<u32, &'static str> {
+		if StorageVersion::get() != Releases::V3 {
+			return Err("token storage is not at V3");
+		}
+		let set = StorageIterator::<bool>::new(MODULE, b"Roles").filter(|(_, v)| *v).count() +
+			StorageIterator::<bool>::new(MODULE, b"Whitelist").filter(|(_, v)| *v).count() +
+			StorageIterator::<BalanceOf<T>>::new(MODULE, b"Allowance").filter(|(_, v)| !v.is_zero()).count() +
+			StorageIterator::<bool>::new(MODULE, b"Freezed").filter(|(_, v)| *v).count() +
+			StorageIterator::<BalanceOf<T>>::new(MODULE, b"FrozenBalance").filter(|(_, v)| !v.is_zero()).count();
+		Ok(set as u32)
+	}
+
+	/// Check the `entries` counted by `pre_migrate` are all in the new layout.
+	#[cfg(any(feature = "try-runtime", test))]
+	pub fn post_migrate<T: Trait>(entries: u32) -> Result<(), &'static str> {
+		if StorageVersion::get() != Releases::V4 {
+			return Err("token storage was not bumped to V4");
+		}
+		let migrated = <Roles<T>>::iter().count() +
+			<Whitelist<T>>::iter().count() +
+			<Allowance<T>>::iter().count() +
+			<Freezed<T>>::iter().count() +
+			<FrozenBalance<T>>::iter().count();
+		if migrated as u32 != entries {
+			return Err("token permission or freeze entries were lost");
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,147 @@
+use crate::{Module, Trait, TokenIndex, TransferHook, KycProvider};
+use sp_core::H256;
+use frame_support::{impl_outer_origin, parameter_types, dispatch::{DispatchError, DispatchResult}, traits::Get, weights::Weight};
+use std::cell::RefCell;
+use sp_runtime::{
+	traits::{BlakeTwo256, IdentityLookup}, testing::Header, Perbill, Permill,
+};
+use frame_system as system;
+
+impl_outer_origin! {
+	pub enum Origin for Test {}
+}
+
+#[derive(Clone, Eq, PartialEq)]
+pub struct Test;
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const MaximumBlockWeight: Weight = 1024;
+	pub const MaximumBlockLength: u32 = 2 * 1024;
+	pub const AvailableBlockRatio: Perbill = Perbill::from_percent(75);
+	pub const ExistentialDeposit: u128 = 1;
+	pub const TokenDeposit: u128 = 10;
+	pub const MetadataDepositPerByte: u128 = 1;
+	pub SnapshotExcluded: Vec<u64> = vec![ESCROW];
+	pub EscrowAccounts: Vec<u64> = vec![ESCROW];
+	pub const MaxClaimsCleared: u32 = 2;
+	pub const MinVestedShare: Permill = Permill::from_percent(5);
+}
+
+impl system::Trait for Test {
+	type BaseCallFilter = ();
+	type Origin = Origin;
+	type Call = ();
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = ();
+	type BlockHashCount = BlockHashCount;
+	type MaximumBlockWeight = MaximumBlockWeight;
+	type DbWeight = frame_support::weights::constants::RocksDbWeight;
+	type BlockExecutionWeight = ();
+	type ExtrinsicBaseWeight = ();
+	type MaximumExtrinsicWeight = MaximumBlockWeight;
+	type MaximumBlockLength = MaximumBlockLength;
+	type AvailableBlockRatio = AvailableBlockRatio;
+	type Version = ();
+	type PalletInfo = ();
+	type AccountData = balances::AccountData<u128>;
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+}
+
+impl balances::Trait for Test {
+	type MaxLocks = ();
+	type Balance = u128;
+	type Event = ();
+	type DustRemoval = ();
+	type ExistentialDeposit = ExistentialDeposit;
+	type AccountStore = System;
+	type WeightInfo = ();
+}
+
+/// Rejects every transfer and mint to `BLOCKED`.
+pub struct BlockList;
+
+impl TransferHook<u64, u128> for BlockList {
+	fn on_transfer(_: TokenIndex, _: Option<&u64>, to: &u64, _: u128) -> DispatchResult {
+		if *to == BLOCKED {
+			return Err(DispatchError::Other("blocked"));
+		}
+		Ok(())
+	}
+}
+
+thread_local! {
+	static UNIQUE_SYMBOLS: RefCell<bool> = RefCell::new(true);
+}
+
+/// The symbol registry is on unless a test turns it off with `set_unique_symbols`.
+pub struct UniqueSymbols;
+
+impl Get<bool> for UniqueSymbols {
+	fn get() -> bool {
+		UNIQUE_SYMBOLS.with(|v| *v.borrow())
+	}
+}
+
+pub fn set_unique_symbols(unique: bool) {
+	UNIQUE_SYMBOLS.with(|v| *v.borrow_mut() = unique);
+}
+
+/// `ALICE` is verified to level 2, `BOB` to level 1, everyone else not at all.
+pub struct Kyc;
+
+impl KycProvider<u64> for Kyc {
+	fn kyc_level(who: &u64) -> u8 {
+		match *who {
+			ALICE => 2,
+			BOB => 1,
+			_ => 0,
+		}
+	}
+}
+
+impl Trait for Test {
+	type Event = ();
+	type Currency = Balances;
+	type TransferHook = BlockList;
+	type KycProvider = Kyc;
+	type TokenDeposit = TokenDeposit;
+	type MetadataDepositPerByte = MetadataDepositPerByte;
+	type RegistryOrigin = system::EnsureRoot<u64>;
+	type UniqueSymbols = UniqueSymbols;
+	type SnapshotExcluded = SnapshotExcluded;
+	type EscrowAccounts = EscrowAccounts;
+	type MaxClaimsCleared = MaxClaimsCleared;
+	type MinVestedShare = MinVestedShare;
+}
+
+pub type System = system::Module<Test>;
+pub type Balances = balances::Module<Test>;
+pub type Token = Module<Test>;
+
+pub const OWNER: u64 = 1;
+pub const ALICE: u64 = 2;
+pub const BOB: u64 = 3;
+pub const BLOCKED: u64 = 4;
+/// Holds tokens for others, like exchange escrow, so is left out of snapshots.
+pub const ESCROW: u64 = 5;
+
+/// Native balance `OWNER` starts with.
+pub const ENDOWMENT: u128 = 1_000;
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	set_unique_symbols(true);
+	let mut t = system::GenesisConfig::default().build_storage::<Test>().unwrap();
+	balances::GenesisConfig::<Test> {
+		balances: vec![(OWNER, ENDOWMENT)],
+	}.assimilate_storage(&mut t).unwrap();
+	t.into()
+}
@@ -1,23 +1,706 @@
-use crate::{Error, mock::*};
-use frame_support::{assert_ok, assert_noop};
+use crate::{mock::*, Allowance, Balance, Claimed, Error, Freezed, FrozenBalance, Roles, Whitelist, HolderCount, Releases, StorageVersion, TokenRole, Tokens, VestingInfo};
+use frame_support::{
+	assert_noop, assert_ok,
+	dispatch::GetDispatchInfo,
+	traits::{OnFinalize, OnInitialize, OnRuntimeUpgrade},
+};
+
+const SUPPLY: u128 = 1_000;
+/// Deposit `setup` reserves: the base plus one per byte of "Token" and "TKN".
+const DEPOSIT: u128 = 10 + 8;
+
+/// Token 0 is created by `OWNER`, who holds its whole supply.
+fn setup() {
+	assert_ok!(Token::create(Origin::signed(OWNER), b"Token".to_vec(), b"TKN".to_vec(), SUPPLY));
+}
+
+/// Replace `who`'s balance of `token` with one stored as V0 and V1 did, keyed by a tuple.
+fn put_v1_balance(token: u32, who: u64, balance: u128) {
+	use frame_support::{storage::migration::put_storage_value, Blake2_128Concat, StorageHasher};
+	use parity_scale_codec::Encode;
+
+	<Balance<Test>>::remove(token, who);
+	HolderCount::remove(token);
+	put_storage_value(b"TokenStore", b"Balance", &Blake2_128Concat::hash(&(token, who).encode()), balance);
+}
+
+#[test]
+fn create_credits_the_supply_to_the_caller() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_eq!(Token::token_count(), 1);
+		assert_eq!(Token::supply(0), SUPPLY);
+		assert_eq!(Token::balance(0, OWNER), SUPPLY);
+		assert_eq!(Token::owner(0), OWNER);
+	});
+}
+
+#[test]
+fn mint_cannot_overflow_the_supply() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(Token::mint(Origin::signed(OWNER), 0, u128::max_value()), Error::<Test>::Overflow);
+	});
+}
+
+#[test]
+fn transfer_moves_balance() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_eq!(Token::balance(0, OWNER), SUPPLY - 100);
+		assert_eq!(Token::balance(0, ALICE), 100);
+		assert_noop!(
+			Token::transfer(Origin::signed(ALICE), 0, BOB, 200),
+			Error::<Test>::InsufficientBalance
+		);
+	});
+}
+
+#[test]
+fn spend_is_bounded_by_the_allowance() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::allow(Origin::signed(OWNER), 0, ALICE, 50));
+		assert_noop!(
+			Token::spend(Origin::signed(ALICE), 0, OWNER, 51),
+			Error::<Test>::InsufficientAllowance
+		);
+		assert_ok!(Token::spend(Origin::signed(ALICE), 0, OWNER, 50));
+		assert_eq!(Token::balance(0, ALICE), 50);
+		// The allowance is used up, so it cannot be spent twice.
+		assert_eq!(Token::allowance(0, (OWNER, ALICE)), 0);
+		assert_noop!(
+			Token::spend(Origin::signed(ALICE), 0, OWNER, 1),
+			Error::<Test>::InsufficientAllowance
+		);
+	});
+}
+
+#[test]
+fn transfer_from_sends_to_any_recipient() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::allow(Origin::signed(OWNER), 0, ALICE, 10));
+		assert_ok!(Token::increase_allowance(Origin::signed(OWNER), 0, ALICE, 90));
+		assert_ok!(Token::decrease_allowance(Origin::signed(OWNER), 0, ALICE, 20));
+		assert_eq!(Token::allowance(0, (OWNER, ALICE)), 80);
+		assert_noop!(
+			Token::decrease_allowance(Origin::signed(OWNER), 0, ALICE, 81),
+			Error::<Test>::InsufficientAllowance
+		);
+
+		assert_ok!(Token::transfer_from(Origin::signed(ALICE), 0, OWNER, BOB, 30));
+		assert_eq!(Token::balance(0, BOB), 30);
+		assert_eq!(Token::balance(0, ALICE), 0);
+		assert_eq!(Token::allowance(0, (OWNER, ALICE)), 50);
+	});
+}
+
+#[test]
+fn transfers_respect_pause_and_recipient_freeze() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::allow(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_ok!(Token::freeze(Origin::signed(OWNER), BOB, 0));
+		assert_noop!(
+			Token::transfer(Origin::signed(OWNER), 0, BOB, 10),
+			Error::<Test>::RecipientFreezed
+		);
+		assert_noop!(
+			Token::transfer_from(Origin::signed(ALICE), 0, OWNER, BOB, 10),
+			Error::<Test>::RecipientFreezed
+		);
+
+		assert_ok!(Token::pause(Origin::signed(OWNER), 0, true));
+		assert_noop!(
+			Token::transfer_from(Origin::signed(ALICE), 0, OWNER, ALICE, 10),
+			Error::<Test>::TokenPaused
+		);
+	});
+}
+
+#[test]
+fn ownership_moves_once_the_new_owner_accepts() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(
+			Token::transfer_ownership(Origin::signed(ALICE), 0, ALICE),
+			Error::<Test>::NotTokenOwner
+		);
+		assert_ok!(Token::transfer_ownership(Origin::signed(OWNER), 0, ALICE));
+		assert_eq!(Token::owner(0), OWNER);
+		assert_noop!(
+			Token::accept_ownership(Origin::signed(BOB), 0),
+			Error::<Test>::NotPendingOwner
+		);
+
+		assert_ok!(Token::accept_ownership(Origin::signed(ALICE), 0));
+		assert_eq!(Token::owner(0), ALICE);
+		assert_eq!(Token::pending_owner(0), None);
+		assert_noop!(Token::mint(Origin::signed(OWNER), 0, 1), Error::<Test>::MissingRole);
+		assert_ok!(Token::mint(Origin::signed(ALICE), 0, 1));
+	});
+}
+
+#[test]
+fn roles_are_granted_and_revoked_by_the_owner() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(
+			Token::grant_role(Origin::signed(ALICE), 0, TokenRole::Minter, ALICE),
+			Error::<Test>::NotTokenOwner
+		);
+		assert_ok!(Token::grant_role(Origin::signed(OWNER), 0, TokenRole::Minter, ALICE));
+		assert_ok!(Token::mint(Origin::signed(ALICE), 0, 10));
+		assert_eq!(Token::balance(0, ALICE), 10);
+		assert_eq!(Token::supply(0), SUPPLY + 10);
+
+		// Each role only covers its own calls.
+		assert_noop!(Token::burn(Origin::signed(ALICE), 0, 10), Error::<Test>::MissingRole);
+		assert_noop!(Token::pause(Origin::signed(ALICE), 0, true), Error::<Test>::MissingRole);
+		assert_ok!(Token::grant_role(Origin::signed(OWNER), 0, TokenRole::Pauser, BOB));
+		assert_ok!(Token::pause(Origin::signed(BOB), 0, true));
+		assert!(Token::paused(0));
+
+		assert_ok!(Token::revoke_role(Origin::signed(OWNER), 0, TokenRole::Minter, ALICE));
+		assert_noop!(Token::mint(Origin::signed(ALICE), 0, 10), Error::<Test>::MissingRole);
+		assert_noop!(Token::mint(Origin::signed(OWNER), 1, 10), Error::<Test>::TokenNotFound);
+	});
+}
+
+#[test]
+fn v0_owner_map_migrates_into_token_info() {
+	use crate::migrations::v1;
+	use frame_support::{storage::migration::put_storage_value, Blake2_128Concat, StorageHasher};
+	use parity_scale_codec::Encode;
+
+	new_test_ext().execute_with(|| {
+		// In V0 `create` recorded the caller in `Owner`, whatever owner it was given.
+		assert_ok!(Token::create(Origin::signed(OWNER), b"Token".to_vec(), b"TKN".to_vec(), SUPPLY));
+		<Tokens<Test>>::mutate(0, |info| info.owner = ALICE);
+		put_storage_value(b"TokenStore", b"Owner", &Blake2_128Concat::hash(&0u32.encode()), OWNER);
+		put_v1_balance(0, OWNER, SUPPLY);
+
+		assert_eq!(StorageVersion::get(), Releases::V0);
+		let owners = v1::pre_migrate::<Test>().unwrap();
+		assert_eq!(owners, vec![(0, OWNER)]);
+
+		Token::on_runtime_upgrade();
+
+		assert_eq!(v1::post_migrate::<Test>(owners), Ok(()));
+		assert_eq!(StorageVersion::get(), Releases::V4);
+		assert_eq!(Token::owner(0), OWNER);
+		assert_ok!(Token::mint(Origin::signed(OWNER), 0, 1));
+	});
+}
+
+#[test]
+fn v1_balances_migrate_into_the_double_map() {
+	use crate::migrations::v2;
+
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		StorageVersion::put(Releases::V1);
+		put_v1_balance(0, OWNER, SUPPLY - 100);
+		put_v1_balance(0, ALICE, 100);
+		// V1 kept zero balances around after transfers.
+		put_v1_balance(0, BOB, 0);
+
+		let balances = v2::pre_migrate::<Test>().unwrap();
+		assert_eq!(balances.len(), 2);
+
+		Token::on_runtime_upgrade();
+
+		assert_eq!(v2::post_migrate::<Test>(balances), Ok(()));
+		assert_eq!(StorageVersion::get(), Releases::V4);
+		assert_eq!(Token::balance(0, ALICE), 100);
+		assert!(!<Balance<Test>>::contains_key(0, BOB));
+		assert_eq!(Token::holder_count(0), 2);
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 100));
+	});
+}
+
+#[test]
+fn v2_symbols_are_registered() {
+	use crate::{migrations::v3, Symbols};
+
+	new_test_ext().execute_with(|| {
+		setup();
+		StorageVersion::put(Releases::V2);
+		Symbols::remove(b"TKN".to_vec());
+
+		let tokens = v3::pre_migrate::<Test>().unwrap();
+		Token::on_runtime_upgrade();
+
+		assert_eq!(v3::post_migrate::<Test>(tokens), Ok(()));
+		assert_eq!(StorageVersion::get(), Releases::V4);
+		assert_eq!(Token::token_by_symbol(b"TKN".to_vec()), Some(0));
+	});
+}
+
+#[test]
+fn holders_are_counted_and_snapshotted() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_eq!(Token::holder_count(0), 1);
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_eq!(Token::holder_count(0), 2);
+
+		System::set_block_number(5);
+		assert_noop!(Token::snapshot(Origin::signed(ALICE), 0), Error::<Test>::NotTokenOwner);
+		assert_ok!(Token::snapshot(Origin::signed(OWNER), 0));
+		let snapshot = Token::snapshots(0).unwrap();
+		assert_eq!((snapshot.token, snapshot.block, snapshot.supply, snapshot.holders), (0, 5, SUPPLY, 2));
+
+		// Later transfers leave the snapshot as it was.
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 200));
+		assert_eq!(Token::holder_count(0), 2);
+		assert_eq!(Token::balance_at(0, &ALICE), 200);
+		assert_eq!(Token::balance_at(0, &BOB), 0);
+		assert_eq!(Token::holders(0).len(), 2);
+
+		// Balances are recorded as they change, so snapshots cost the same for any holders.
+		let two_holders = crate::Call::<Test>::snapshot(0).get_dispatch_info().weight;
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 1));
+		let three_holders = crate::Call::<Test>::snapshot(0).get_dispatch_info().weight;
+		assert_eq!(three_holders, two_holders);
+
+		// Sending to oneself changes nothing.
+		assert_ok!(Token::transfer(Origin::signed(BOB), 0, BOB, 200));
+		assert_eq!(Token::balance(0, BOB), 200);
+	});
+}
+
+#[test]
+fn holders_burn_and_redeem_their_own_tokens() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+
+		assert_ok!(Token::burn_self(Origin::signed(ALICE), 0, 30));
+		assert_eq!(Token::balance(0, ALICE), 70);
+		assert_eq!(Token::supply(0), SUPPLY - 30);
+		assert_noop!(Token::burn_self(Origin::signed(ALICE), 0, 71), Error::<Test>::InsufficientBalance);
+
+		assert_noop!(
+			Token::redeem(Origin::signed(ALICE), 0, 10, vec![0; crate::MAX_REFERENCE_LENGTH + 1]),
+			Error::<Test>::ReferenceTooLong
+		);
+		assert_ok!(Token::redeem(Origin::signed(ALICE), 0, 70, b"IBAN-REF-1".to_vec()));
+		assert_eq!(Token::balance(0, ALICE), 0);
+		assert_eq!(Token::supply(0), SUPPLY - 100);
+	});
+}
+
+#[test]
+fn burn_from_consumes_the_allowance() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_ok!(Token::allow(Origin::signed(ALICE), 0, OWNER, 60));
+
+		assert_ok!(Token::burn_from(Origin::signed(OWNER), 0, ALICE, 40));
+		assert_eq!(Token::balance(0, ALICE), 60);
+		assert_eq!(Token::allowance(0, (ALICE, OWNER)), 20);
+		assert_noop!(
+			Token::burn_from(Origin::signed(OWNER), 0, ALICE, 21),
+			Error::<Test>::InsufficientAllowance
+		);
+	});
+}
 
 #[test]
-fn it_works_for_default_value() {
+fn frozen_amounts_cannot_leave_the_account() {
 	new_test_ext().execute_with(|| {
-		// Dispatch a signed extrinsic.
-		assert_ok!(TemplateModule::do_something(Origin::signed(1), 42));
-		// Read pallet storage and assert an expected result.
-		assert_eq!(TemplateModule::something(), Some(42));
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_ok!(Token::freeze_balance(Origin::signed(OWNER), ALICE, 0, 60));
+		assert_eq!(Token::free_balance(0, &ALICE), 40);
+		// Thawing the account does not release a frozen amount.
+		assert_ok!(Token::thaw(Origin::signed(OWNER), ALICE, 0));
+		assert_eq!(Token::free_balance(0, &ALICE), 40);
+
+		assert_noop!(
+			Token::transfer(Origin::signed(ALICE), 0, BOB, 41),
+			Error::<Test>::BalanceFrozen
+		);
+		assert_noop!(Token::burn_self(Origin::signed(ALICE), 0, 41), Error::<Test>::BalanceFrozen);
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 40));
+		// Frozen amounts do not stop the account receiving.
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 10));
+
+		assert_ok!(Token::thaw_balance(Origin::signed(OWNER), ALICE, 0, 60));
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 70));
+	});
+}
+
+#[test]
+fn internal_transfers_enforce_freezes() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::freeze(Origin::signed(OWNER), ALICE, 0));
+		assert_noop!(Token::transfer_(0, OWNER, ALICE, 1), Error::<Test>::RecipientFreezed);
+		assert_noop!(Token::transfer_(0, ALICE, OWNER, 0), Error::<Test>::AccountFreezed);
+
+		// Pauses only stop holders, not internal transfers such as exchange settlement.
+		assert_ok!(Token::pause(Origin::signed(OWNER), 0, true));
+		assert_ok!(Token::transfer_(0, OWNER, BOB, 1));
 	});
 }
 
 #[test]
-fn correct_error_for_none_value() {
+fn restricted_tokens_only_reach_whitelisted_accounts() {
 	new_test_ext().execute_with(|| {
-		// Ensure the expected error is thrown when no value is present.
+		setup();
+		assert_ok!(Token::grant_role(Origin::signed(OWNER), 0, TokenRole::Compliance, BOB));
+		assert_noop!(Token::set_restricted(Origin::signed(BOB), 0, true), Error::<Test>::NotTokenOwner);
+		assert_ok!(Token::set_restricted(Origin::signed(OWNER), 0, true));
+
 		assert_noop!(
-			TemplateModule::cause_error(Origin::signed(1)),
-			Error::<Test>::NoneValue
+			Token::transfer(Origin::signed(OWNER), 0, ALICE, 10),
+			Error::<Test>::NotWhitelisted
 		);
+		assert_noop!(Token::whitelist(Origin::signed(ALICE), 0, ALICE), Error::<Test>::MissingRole);
+		assert_ok!(Token::whitelist(Origin::signed(BOB), 0, ALICE));
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 10));
+
+		// The owner is not whitelisted, so it cannot mint to itself.
+		assert_noop!(Token::mint(Origin::signed(OWNER), 0, 10), Error::<Test>::NotWhitelisted);
+
+		// Holders off the whitelist can still send what they hold.
+		assert_ok!(Token::unwhitelist(Origin::signed(BOB), 0, ALICE));
+		assert!(!Token::can_receive(0, &ALICE));
+		assert_ok!(Token::whitelist(Origin::signed(BOB), 0, OWNER));
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, OWNER, 10));
+	});
+}
+
+#[test]
+fn transfer_hook_can_reject_transfers_and_mints() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(
+			Token::transfer(Origin::signed(OWNER), 0, BLOCKED, 10),
+			sp_runtime::DispatchError::Other("blocked")
+		);
+		assert_ok!(Token::grant_role(Origin::signed(OWNER), 0, TokenRole::Minter, BLOCKED));
+		assert_noop!(Token::mint(Origin::signed(BLOCKED), 0, 10), sp_runtime::DispatchError::Other("blocked"));
+	});
+}
+
+#[test]
+fn tokens_can_require_a_kyc_level() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(Token::set_required_kyc(Origin::signed(ALICE), 0, 2), Error::<Test>::MissingRole);
+		assert_ok!(Token::set_required_kyc(Origin::signed(OWNER), 0, 2));
+
+		assert_noop!(Token::transfer(Origin::signed(OWNER), 0, BOB, 10), Error::<Test>::InsufficientKyc);
+		assert!(!Token::can_receive(0, &BOB));
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 10));
+		// Escrow accounts hold for others and need no level of their own.
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ESCROW, 10));
+
+		// Unverified holders keep what they hold and can still send it on.
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, OWNER, 5));
+		assert_noop!(Token::mint(Origin::signed(OWNER), 0, 10), Error::<Test>::InsufficientKyc);
+
+		assert_ok!(Token::set_required_kyc(Origin::signed(OWNER), 0, 1));
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 5));
+	});
+}
+
+#[test]
+fn snapshots_keep_the_balances_of_their_block() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_ok!(Token::snapshot(Origin::signed(OWNER), 0));
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 40));
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 10));
+		assert_ok!(Token::snapshot(Origin::signed(OWNER), 0));
+		assert_ok!(Token::snapshot(Origin::signed(OWNER), 0));
+		assert_ok!(Token::transfer(Origin::signed(BOB), 0, ALICE, 5));
+		assert_ok!(Token::snapshot(Origin::signed(OWNER), 0));
+
+		// Only the first change after a snapshot is recorded.
+		assert_eq!(Token::checkpoints(0, ALICE), vec![(0, 100), (2, 50)]);
+		assert_eq!(Token::checkpoints(0, BOB), vec![(0, 0), (2, 50)]);
+		for (snapshot, alice, bob) in [(0, 100, 0), (1, 50, 50), (2, 50, 50), (3, 55, 45)].iter() {
+			assert_eq!(Token::balance_at(*snapshot, &ALICE), *alice);
+			assert_eq!(Token::balance_at(*snapshot, &BOB), *bob);
+		}
+		assert_eq!(Token::snapshots(1).unwrap().supply, SUPPLY);
+
+		// Balances that never changed are read as they are now.
+		assert_eq!(Token::balance_at(0, &OWNER), SUPPLY - 100);
+		assert_eq!(Token::balance_at(4, &ALICE), 0);
+	});
+}
+
+#[test]
+fn native_distributions_are_claimed_pro_rata_until_they_expire() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 250));
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, BOB, 250));
+		System::set_block_number(1);
+
+		assert_noop!(Token::distribute(Origin::signed(ALICE), 0, None, 100, 10), Error::<Test>::NotTokenOwner);
+		assert_noop!(Token::distribute(Origin::signed(OWNER), 0, None, 100, 0), Error::<Test>::InvalidExpiry);
+		assert_ok!(Token::distribute(Origin::signed(OWNER), 0, None, 100, 10));
+		assert_eq!(Balances::free_balance(Token::distribution_pool()), 100);
+		assert_eq!(Balances::free_balance(OWNER), ENDOWMENT - DEPOSIT - 100);
+
+		// Shares follow the snapshot, not later transfers.
+		assert_ok!(Token::transfer(Origin::signed(BOB), 0, ALICE, 250));
+		assert_eq!(Token::pending_claim(0, &ALICE), 25);
+		assert_eq!(Token::pending_claims(&OWNER), vec![(0, 50)]);
+
+		assert_ok!(Token::claim(Origin::signed(ALICE), 0));
+		assert_eq!(Balances::free_balance(ALICE), 25);
+		assert_eq!(Token::pending_claim(0, &ALICE), 0);
+		assert_noop!(Token::claim(Origin::signed(ALICE), 0), Error::<Test>::AlreadyClaimed);
+		assert_noop!(Token::claim(Origin::signed(4), 0), Error::<Test>::NothingToClaim);
+
+		// What is left at expiry goes back to the issuer.
+		Token::on_finalize(11);
+		assert_eq!(Balances::free_balance(OWNER), ENDOWMENT - DEPOSIT - 25);
+		assert_eq!(Balances::free_balance(Token::distribution_pool()), 0);
+		assert_noop!(Token::claim(Origin::signed(BOB), 0), Error::<Test>::DistributionNotFound);
+	});
+}
+
+#[test]
+fn escrow_and_the_pool_are_left_out_of_distributions() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 100));
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ESCROW, 500));
+		// Token 0 paid out in token 0 puts the pool among its holders.
+		assert_ok!(Token::distribute(Origin::signed(OWNER), 0, Some(0), 200, 10));
+		assert_eq!(Token::balance(0, Token::distribution_pool()), 200);
+
+		let snapshot = Token::snapshots(0).unwrap();
+		assert_eq!((snapshot.supply, snapshot.holders), (300, 2));
+		assert_eq!(Token::pending_claim(0, &ESCROW), 0);
+		assert_eq!(Token::pending_claim(0, &Token::distribution_pool()), 0);
+		assert_eq!(Token::pending_claim(0, &OWNER), 133);
+		assert_eq!(Token::pending_claim(0, &ALICE), 66);
+
+		// Nothing but escrow and the pool left to pay.
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ESCROW, 200));
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, ESCROW, 100));
+		assert_noop!(Token::distribute(Origin::signed(OWNER), 0, None, 100, 10), Error::<Test>::NoHolders);
+		assert_eq!(Balances::free_balance(Token::distribution_pool()), 0);
+	});
+}
+
+#[test]
+fn claim_records_are_cleared_a_few_per_block() {
+	new_test_ext().execute_with(|| {
+		setup();
+		System::set_block_number(1);
+		for who in [ALICE, BOB, ESCROW + 1].iter() {
+			assert_ok!(Token::transfer(Origin::signed(OWNER), 0, *who, 100));
+		}
+		assert_ok!(Token::distribute(Origin::signed(OWNER), 0, None, 100, 10));
+		for who in [ALICE, BOB, ESCROW + 1].iter() {
+			assert_ok!(Token::claim(Origin::signed(*who), 0));
+		}
+		assert_eq!(<Claimed<Test>>::iter_prefix(0).count(), 3);
+
+		Token::on_finalize(11);
+		assert_eq!(Token::claims_to_clear(), vec![0]);
+		assert!(Token::on_initialize(12) > Token::on_initialize(13));
+		assert_eq!(<Claimed<Test>>::iter_prefix(0).count(), 0);
+		assert!(Token::claims_to_clear().is_empty());
+	});
+}
+
+#[test]
+fn token_distributions_are_paid_from_the_pool() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_ok!(Token::transfer(Origin::signed(OWNER), 0, ALICE, 500));
+		assert_ok!(Token::create(Origin::signed(OWNER), b"Reward".to_vec(), b"RWD".to_vec(), 300));
+
+		assert_noop!(
+			Token::distribute(Origin::signed(OWNER), 0, Some(1), 301, 10),
+			Error::<Test>::InsufficientBalance
+		);
+		assert_ok!(Token::distribute(Origin::signed(OWNER), 0, Some(1), 300, 10));
+		assert_eq!(Token::balance(1, OWNER), 0);
+		assert_eq!(Token::balance(1, Token::distribution_pool()), 300);
+
+		assert_ok!(Token::claim(Origin::signed(ALICE), 0));
+		assert_eq!(Token::balance(1, ALICE), 150);
+
+		Token::on_finalize(10);
+		assert_eq!(Token::balance(1, OWNER), 150);
+		assert_eq!(Token::balance(1, Token::distribution_pool()), 0);
+	});
+}
+
+#[test]
+fn vested_transfers_unlock_after_the_cliff() {
+	new_test_ext().execute_with(|| {
+		setup();
+		System::set_block_number(1);
+		let schedule = VestingInfo { locked: 100, per_block: 10, starting_block: 1, cliff: 5 };
+		assert_noop!(
+			Token::vested_transfer(Origin::signed(OWNER), 0, ALICE, VestingInfo { per_block: 0, ..schedule }),
+			Error::<Test>::InvalidSchedule
+		);
+		// A dust schedule, below 5% of the supply, cannot take the account's slot.
+		assert_noop!(
+			Token::vested_transfer(Origin::signed(OWNER), 0, ALICE, VestingInfo { locked: 49, ..schedule }),
+			Error::<Test>::VestedAmountTooLow
+		);
+		assert_ok!(Token::vested_transfer(Origin::signed(OWNER), 0, ALICE, schedule));
+		assert_noop!(
+			Token::vested_transfer(Origin::signed(OWNER), 0, ALICE, schedule),
+			Error::<Test>::ExistingVestingSchedule
+		);
+		assert_noop!(Token::transfer(Origin::signed(ALICE), 0, BOB, 1), Error::<Test>::BalanceVesting);
+
+		// Four blocks have vested by the cliff.
+		System::set_block_number(5);
+		assert_eq!(Token::vesting_balance(0, &ALICE), 60);
+		assert_noop!(Token::transfer(Origin::signed(ALICE), 0, BOB, 41), Error::<Test>::BalanceVesting);
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 40));
+		assert_ok!(Token::vest(Origin::signed(ALICE), 0));
+		assert!(Token::vesting(0, ALICE).is_some());
+
+		System::set_block_number(11);
+		assert_ok!(Token::vest(Origin::signed(ALICE), 0));
+		assert!(Token::vesting(0, ALICE).is_none());
+		assert_noop!(Token::vest(Origin::signed(ALICE), 0), Error::<Test>::NotVesting);
+		assert_ok!(Token::transfer(Origin::signed(ALICE), 0, BOB, 60));
+	});
+}
+
+#[test]
+fn creation_holds_a_deposit_until_the_token_is_destroyed() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_eq!(Balances::reserved_balance(OWNER), DEPOSIT);
+		assert_noop!(
+			Token::create(Origin::signed(ALICE), b"Other".to_vec(), b"OTH".to_vec(), SUPPLY),
+			balances::Error::<Test, balances::DefaultInstance>::InsufficientBalance
+		);
+
+		// A longer name costs more.
+		assert_ok!(Token::edit(Origin::signed(OWNER), 0, b"Token Two".to_vec(), b"TKN".to_vec()));
+		assert_eq!(Balances::reserved_balance(OWNER), DEPOSIT + 4);
+
+		assert_ok!(Token::grant_role(Origin::signed(OWNER), 0, TokenRole::Freezer, ALICE));
+		assert_ok!(Token::whitelist(Origin::signed(OWNER), 0, ALICE));
+		assert_ok!(Token::allow(Origin::signed(OWNER), 0, ALICE, 10));
+		assert_ok!(Token::freeze(Origin::signed(ALICE), BOB, 0));
+		assert_ok!(Token::freeze_balance(Origin::signed(ALICE), BOB, 0, 10));
+
+		assert_noop!(Token::destroy(Origin::signed(OWNER), 0), Error::<Test>::SupplyNotZero);
+		assert_ok!(Token::burn_self(Origin::signed(OWNER), 0, SUPPLY));
+		assert_noop!(Token::destroy(Origin::signed(ALICE), 0), Error::<Test>::NotTokenOwner);
+		assert_ok!(Token::destroy(Origin::signed(OWNER), 0));
+		assert_eq!(Balances::reserved_balance(OWNER), 0);
+		assert_eq!(Token::token_by_symbol(b"TKN".to_vec()), None);
+
+		// Nothing of the token is left behind.
+		assert_eq!(<Roles<Test>>::iter_prefix(0).count(), 0);
+		assert_eq!(<Whitelist<Test>>::iter_prefix(0).count(), 0);
+		assert_eq!(<Allowance<Test>>::iter_prefix(0).count(), 0);
+		assert_eq!(<Freezed<Test>>::iter_prefix(0).count(), 0);
+		assert_eq!(<FrozenBalance<Test>>::iter_prefix(0).count(), 0);
+		assert_noop!(Token::mint(Origin::signed(OWNER), 0, 1), Error::<Test>::TokenNotFound);
+	});
+}
+
+#[test]
+fn symbols_are_unique_and_can_be_reserved() {
+	new_test_ext().execute_with(|| {
+		setup();
+		assert_noop!(
+			Token::create(Origin::signed(OWNER), b"Copy".to_vec(), b"tkn".to_vec(), SUPPLY),
+			Error::<Test>::SymbolTaken
+		);
+
+		assert_noop!(
+			Token::reserve_symbol(Origin::signed(OWNER), b"DCB".to_vec(), Some(ALICE)),
+			sp_runtime::traits::BadOrigin
+		);
+		assert_ok!(Token::reserve_symbol(Origin::root(), b"DCB".to_vec(), Some(ALICE)));
+		assert_noop!(
+			Token::create(Origin::signed(OWNER), b"Coin".to_vec(), b"DCB".to_vec(), SUPPLY),
+			Error::<Test>::SymbolReserved
+		);
+		assert_noop!(
+			Token::edit(Origin::signed(OWNER), 0, b"Token".to_vec(), b"dcb".to_vec()),
+			Error::<Test>::SymbolReserved
+		);
+
+		assert_ok!(Token::reserve_symbol(Origin::root(), b"DCB".to_vec(), None));
+		assert_ok!(Token::edit(Origin::signed(OWNER), 0, b"Token".to_vec(), b"DCB".to_vec()));
+		assert_eq!(Token::token_by_symbol(b"DCB".to_vec()), Some(0));
+		assert_ok!(Token::create(Origin::signed(OWNER), b"Token".to_vec(), b"TKN".to_vec(), SUPPLY));
+	});
+}
+
+#[test]
+fn symbols_can_be_shared_without_the_registry() {
+	new_test_ext().execute_with(|| {
+		set_unique_symbols(false);
+		setup();
+		assert_ok!(Token::create(Origin::signed(OWNER), b"Copy".to_vec(), b"tkn".to_vec(), SUPPLY));
+		assert_ok!(Token::edit(Origin::signed(OWNER), 0, b"Token".to_vec(), b"NEW".to_vec()));
+		assert_ok!(Token::edit(Origin::signed(OWNER), 1, b"Copy".to_vec(), b"new".to_vec()));
+		assert_eq!(Token::token_by_symbol(b"TKN".to_vec()), None);
+		assert_eq!(Token::token_by_symbol(b"NEW".to_vec()), None);
+
+		assert_ok!(Token::reserve_symbol(Origin::root(), b"DCB".to_vec(), Some(ALICE)));
+		assert_noop!(
+			Token::create(Origin::signed(OWNER), b"Coin".to_vec(), b"DCB".to_vec(), SUPPLY),
+			Error::<Test>::SymbolReserved
+		);
+	});
+}
+
+#[test]
+fn v3_permissions_and_freezes_are_keyed_by_token() {
+	use crate::migrations::v4;
+	use frame_support::{storage::migration::put_storage_value, Blake2_128Concat, StorageHasher};
+	use parity_scale_codec::Encode;
+
+	fn put<K: Encode, V: Encode>(item: &[u8], key: K, value: V) {
+		put_storage_value(b"TokenStore", item, &Blake2_128Concat::hash(&key.encode()), value);
+	}
+
+	new_test_ext().execute_with(|| {
+		setup();
+		put(b"Roles", (0u32, TokenRole::Minter, ALICE), true);
+		put(b"Whitelist", (0u32, ALICE), true);
+		put(b"Allowance", (0u32, OWNER, ALICE), 10u128);
+		put(b"Freezed", (0u32, BOB), true);
+		// V3 `thaw` left `false` behind.
+		put(b"Freezed", (0u32, ALICE), false);
+		put(b"FrozenBalance", (0u32, BOB), 5u128);
+		StorageVersion::put(Releases::V3);
+
+		let entries = v4::pre_migrate::<Test>().unwrap();
+		assert_eq!(entries, 5);
+		Token::on_runtime_upgrade();
+
+		assert_eq!(v4::post_migrate::<Test>(entries), Ok(()));
+		assert!(Token::has_role(0, (TokenRole::Minter, ALICE)));
+		assert!(Token::whitelisted(0, ALICE));
+		assert_eq!(Token::allowance(0, (OWNER, ALICE)), 10);
+		assert!(Token::freezed(0, BOB));
+		assert!(!<Freezed<Test>>::contains_key(0, ALICE));
+		assert_eq!(Token::frozen_balance(0, BOB), 5);
+		assert_ok!(Token::mint(Origin::signed(ALICE), 0, 1));
 	});
 }
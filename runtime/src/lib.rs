@@ -7,18 +7,23 @@
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
 use sp_std::prelude::*;
-use sp_core::{crypto::KeyTypeId, OpaqueMetadata};
+use sp_core::{crypto::KeyTypeId, OpaqueMetadata, u32_trait::{_1, _2, _3}};
 use sp_runtime::{
-	ApplyExtrinsicResult, generic, create_runtime_str, impl_opaque_keys, MultiSignature,
-	transaction_validity::{TransactionValidity, TransactionSource},
+	ApplyExtrinsicResult, generic, create_runtime_str, impl_opaque_keys, MultiSignature, ModuleId,
+	curve::PiecewiseLinear,
+	transaction_validity::{TransactionValidity, TransactionSource, TransactionPriority},
 };
 use sp_runtime::traits::{
 	BlakeTwo256, Block as BlockT, IdentityLookup, Verify, IdentifyAccount, NumberFor, Saturating,
+	Convert, OpaqueKeys, AccountIdConversion,
 };
 use sp_api::impl_runtime_apis;
 use sp_consensus_aura::sr25519::AuthorityId as AuraId;
 use pallet_grandpa::{AuthorityId as GrandpaId, AuthorityList as GrandpaAuthorityList};
 use pallet_grandpa::fg_primitives;
+use pallet_im_online::sr25519::AuthorityId as ImOnlineId;
+use pallet_identity::Judgement;
+use pallet_session::historical as pallet_session_historical;
 use sp_version::RuntimeVersion;
 #[cfg(feature = "std")]
 use sp_version::NativeVersion;
@@ -30,10 +35,11 @@ use pallet_contracts_rpc_runtime_api::ContractExecResult;
 pub use sp_runtime::BuildStorage;
 pub use pallet_timestamp::Call as TimestampCall;
 pub use pallet_balances::Call as BalancesCall;
+pub use pallet_staking::StakerStatus;
 pub use sp_runtime::{Permill, Perbill};
 pub use frame_support::{
 	construct_runtime, parameter_types, StorageValue,
-	traits::{KeyOwnerProofSystem, Randomness},
+	traits::{Get, KeyOwnerProofSystem, Randomness},
 	weights::{
 		Weight, IdentityFee,
 		constants::{BlockExecutionWeight, ExtrinsicBaseWeight, RocksDbWeight, WEIGHT_PER_SECOND},
@@ -87,6 +93,7 @@ pub mod opaque {
 		pub struct SessionKeys {
 			pub aura: Aura,
 			pub grandpa: Grandpa,
+			pub im_online: ImOnline,
 		}
 	}
 }
@@ -95,10 +102,10 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	spec_name: create_runtime_str!("node-template"),
 	impl_name: create_runtime_str!("node-template"),
 	authoring_version: 1,
-	spec_version: 27,
+	spec_version: 57,
 	impl_version: 1,
 	apis: RUNTIME_API_VERSIONS,
-	transaction_version: 1,
+	transaction_version: 3,
 };
 
 pub const MILLISECS_PER_BLOCK: u64 = 6000;
@@ -192,6 +199,147 @@ impl frame_system::Trait for Runtime {
 	type SystemWeightInfo = ();
 }
 
+parameter_types! {
+	pub const SessionPeriod: BlockNumber = HOURS;
+	pub const SessionOffset: BlockNumber = 0;
+	pub const DisabledValidatorsThreshold: Perbill = Perbill::from_percent(17);
+}
+
+impl pallet_session::Trait for Runtime {
+	type Event = Event;
+	type ValidatorId = <Self as frame_system::Trait>::AccountId;
+	type ValidatorIdOf = pallet_staking::StashOf<Self>;
+	type ShouldEndSession = pallet_session::PeriodicSessions<SessionPeriod, SessionOffset>;
+	type NextSessionRotation = pallet_session::PeriodicSessions<SessionPeriod, SessionOffset>;
+	type SessionManager = pallet_session::historical::NoteHistoricalRoot<Self, Staking>;
+	type SessionHandler = <opaque::SessionKeys as OpaqueKeys>::KeyTypeIdProviders;
+	type Keys = opaque::SessionKeys;
+	type DisabledValidatorsThreshold = DisabledValidatorsThreshold;
+	type WeightInfo = ();
+}
+
+impl pallet_session::historical::Trait for Runtime {
+	type FullIdentification = pallet_staking::Exposure<AccountId, Balance>;
+	type FullIdentificationOf = pallet_staking::ExposureOf<Runtime>;
+}
+
+pallet_staking_reward_curve::build! {
+	const REWARD_CURVE: PiecewiseLinear<'static> = curve!(
+		min_inflation: 0_025_000,
+		max_inflation: 0_100_000,
+		ideal_stake: 0_500_000,
+		falloff: 0_050_000,
+		max_piece_count: 40,
+		test_precision: 0_005_000,
+	);
+}
+
+/// Scales balances down to the `u64` vote weights used by staking elections.
+pub struct CurrencyToVoteHandler;
+
+impl CurrencyToVoteHandler {
+	fn factor() -> Balance {
+		(Balances::total_issuance() / u64::max_value() as Balance).max(1)
+	}
+}
+
+impl Convert<Balance, u64> for CurrencyToVoteHandler {
+	fn convert(x: Balance) -> u64 {
+		(x / Self::factor()) as u64
+	}
+}
+
+impl Convert<u128, Balance> for CurrencyToVoteHandler {
+	fn convert(x: u128) -> Balance {
+		x * Self::factor()
+	}
+}
+
+parameter_types! {
+	/// Six hour-long sessions, so four eras a day.
+	pub const SessionsPerEra: sp_staking::SessionIndex = 6;
+	/// 28 days.
+	pub const BondingDuration: pallet_staking::EraIndex = 4 * 28;
+	/// 7 days, a quarter of the bonding duration.
+	pub const SlashDeferDuration: pallet_staking::EraIndex = 4 * 7;
+	pub const RewardCurve: &'static PiecewiseLinear<'static> = &REWARD_CURVE;
+	pub const ElectionLookahead: BlockNumber = HOURS / 4;
+	pub const MaxIterations: u32 = 10;
+	pub MinSolutionScoreBump: Perbill = Perbill::from_rational_approximation(5u32, 10_000);
+	pub const MaxNominatorRewardedPerValidator: u32 = 64;
+	pub const StakingUnsignedPriority: TransactionPriority = TransactionPriority::max_value() / 2;
+	pub OffchainSolutionWeightLimit: Weight = MaximumExtrinsicWeight::get()
+		.saturating_sub(BlockExecutionWeight::get())
+		.saturating_sub(ExtrinsicBaseWeight::get());
+}
+
+impl pallet_staking::Trait for Runtime {
+	type Currency = Balances;
+	type UnixTime = Timestamp;
+	type CurrencyToVote = CurrencyToVoteHandler;
+	type RewardRemainder = ();
+	type Event = Event;
+	type Slash = ();
+	type Reward = ();
+	type SessionsPerEra = SessionsPerEra;
+	type BondingDuration = BondingDuration;
+	type SlashDeferDuration = SlashDeferDuration;
+	type SlashCancelOrigin = EnsureRootOrTwoThirdsCouncil;
+	type SessionInterface = Self;
+	type RewardCurve = RewardCurve;
+	type NextNewSession = Session;
+	type ElectionLookahead = ElectionLookahead;
+	type Call = Call;
+	type MaxIterations = MaxIterations;
+	type MinSolutionScoreBump = MinSolutionScoreBump;
+	type MaxNominatorRewardedPerValidator = MaxNominatorRewardedPerValidator;
+	type UnsignedPriority = StakingUnsignedPriority;
+	type OffchainSolutionWeightLimit = OffchainSolutionWeightLimit;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const UncleGenerations: BlockNumber = 5;
+}
+
+impl pallet_authorship::Trait for Runtime {
+	type FindAuthor = pallet_session::FindAccountFromAuthorIndex<Self, Aura>;
+	type UncleGenerations = UncleGenerations;
+	type FilterUncle = ();
+	type EventHandler = (Staking, ImOnline);
+}
+
+parameter_types! {
+	pub const ImOnlineUnsignedPriority: TransactionPriority = TransactionPriority::max_value();
+}
+
+impl pallet_im_online::Trait for Runtime {
+	type AuthorityId = ImOnlineId;
+	type Event = Event;
+	type SessionDuration = SessionPeriod;
+	type ReportUnresponsiveness = Offences;
+	type UnsignedPriority = ImOnlineUnsignedPriority;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub OffencesWeightSoftLimit: Weight = Perbill::from_percent(60) * MaximumBlockWeight::get();
+}
+
+impl pallet_offences::Trait for Runtime {
+	type Event = Event;
+	type IdentificationTuple = pallet_session::historical::IdentificationTuple<Self>;
+	type OnOffenceHandler = Staking;
+	type WeightSoftLimit = OffencesWeightSoftLimit;
+}
+
+impl<C> frame_system::offchain::SendTransactionTypes<C> for Runtime where
+	Call: From<C>,
+{
+	type Extrinsic = UncheckedExtrinsic;
+	type OverarchingCall = Call;
+}
+
 impl pallet_aura::Trait for Runtime {
 	type AuthorityId = AuraId;
 }
@@ -261,6 +409,101 @@ impl pallet_sudo::Trait for Runtime {
 	type Call = Call;
 }
 
+parameter_types! {
+	pub const CouncilMotionDuration: BlockNumber = 3 * DAYS;
+	pub const CouncilMaxProposals: u32 = 100;
+	pub const CouncilMaxMembers: u32 = 100;
+}
+
+type CouncilCollective = pallet_collective::Instance1;
+impl pallet_collective::Trait<CouncilCollective> for Runtime {
+	type Origin = Origin;
+	type Proposal = Call;
+	type Event = Event;
+	type MotionDuration = CouncilMotionDuration;
+	type MaxProposals = CouncilMaxProposals;
+	type MaxMembers = CouncilMaxMembers;
+	type DefaultVote = pallet_collective::PrimeDefaultVote;
+	type WeightInfo = ();
+}
+
+/// Root, or more than half of the council.
+pub type EnsureRootOrHalfCouncil = frame_system::EnsureOneOf<
+	AccountId,
+	frame_system::EnsureRoot<AccountId>,
+	pallet_collective::EnsureProportionMoreThan<_1, _2, AccountId, CouncilCollective>,
+>;
+
+/// Root, or at least two thirds of the council.
+pub type EnsureRootOrTwoThirdsCouncil = frame_system::EnsureOneOf<
+	AccountId,
+	frame_system::EnsureRoot<AccountId>,
+	pallet_collective::EnsureProportionAtLeast<_2, _3, AccountId, CouncilCollective>,
+>;
+
+parameter_types! {
+	pub const MaxWellKnownNodes: u32 = 8;
+	pub const MaxPeerIdLength: u32 = 128;
+}
+
+impl pallet_node_authorization::Trait for Runtime {
+	type Event = Event;
+	type MaxWellKnownNodes = MaxWellKnownNodes;
+	type MaxPeerIdLength = MaxPeerIdLength;
+	type AddOrigin = EnsureRootOrHalfCouncil;
+	type RemoveOrigin = EnsureRootOrHalfCouncil;
+	type SwapOrigin = EnsureRootOrHalfCouncil;
+	type ResetOrigin = EnsureRootOrTwoThirdsCouncil;
+	type WeightInfo = ();
+}
+
+parameter_types! {
+	pub const BasicDeposit: Balance = 10 * DOLLARS;
+	pub const FieldDeposit: Balance = 250 * CENTS;
+	pub const SubAccountDeposit: Balance = 2 * DOLLARS;
+	pub const MaxSubAccounts: u32 = 100;
+	pub const MaxAdditionalFields: u32 = 100;
+	pub const MaxRegistrars: u32 = 20;
+}
+
+impl pallet_identity::Trait for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type BasicDeposit = BasicDeposit;
+	type FieldDeposit = FieldDeposit;
+	type SubAccountDeposit = SubAccountDeposit;
+	type MaxSubAccounts = MaxSubAccounts;
+	type MaxAdditionalFields = MaxAdditionalFields;
+	type MaxRegistrars = MaxRegistrars;
+	type Slashed = ();
+	type ForceOrigin = EnsureRootOrHalfCouncil;
+	type RegistrarOrigin = EnsureRootOrHalfCouncil;
+	type WeightInfo = ();
+}
+
+/// KYC level of an account, from the judgements registrars gave its identity: 2 for
+/// `KnownGood`, 1 for `Reasonable`, and 0 if any registrar found it `LowQuality` or `Erroneous`.
+pub struct IdentityKyc;
+
+impl pallet_token::KycProvider<AccountId> for IdentityKyc {
+	fn kyc_level(who: &AccountId) -> u8 {
+		let registration = match Identity::identity(who) {
+			Some(registration) => registration,
+			None => return 0,
+		};
+		let mut level = 0;
+		for (_, judgement) in registration.judgements.iter() {
+			match judgement {
+				Judgement::KnownGood => level = level.max(2),
+				Judgement::Reasonable => level = level.max(1),
+				Judgement::LowQuality | Judgement::Erroneous => return 0,
+				_ => {},
+			}
+		}
+		level
+	}
+}
+
 parameter_types! {
 	pub const ConfigDepositBase: u64 = 10;
 	pub const FriendDepositFactor: u64 = 1;
@@ -297,14 +540,90 @@ impl pallet_multisig::Trait for Runtime {
 
 
 
+parameter_types! {
+	pub const MaxOpenOrdersPerUser: u32 = 100;
+	pub const MaxOrdersPerSide: u32 = 1_000;
+	pub const MaxTriggersPerMarket: u32 = 64;
+	pub const MaxMatchingSteps: u32 = 32;
+	pub const OrderDeposit: Balance = 10 * CENTS;
+	pub const ListingBond: Balance = 100 * DOLLARS;
+	pub const ListingChallengePeriod: BlockNumber = 7 * DAYS;
+	pub const CandlePeriod: BlockNumber = HOURS;
+	pub const VolumeWindow: BlockNumber = DAYS;
+	pub const OracleGranularity: BlockNumber = 10 * MINUTES;
+	pub const OracleObservations: u32 = 144;
+	pub const ExchangeModuleId: ModuleId = ModuleId(*b"dcb/exch");
+}
+
+/// Keyless `(operation, vault)` accounts of the exchange, derived from `ExchangeModuleId`
+/// so that nobody holds a key to the order escrow or the fees.
+pub fn exchange_accounts() -> (AccountId, AccountId) {
+	(
+		ExchangeModuleId::get().into_account(),
+		ExchangeModuleId::get().into_sub_account(b"vault"),
+	)
+}
+
 impl pallet_exchange::Trait for Runtime {
 	type Event = Event;
 	type Currency = Balances;
+	type MaxOpenOrdersPerUser = MaxOpenOrdersPerUser;
+	type MaxOrdersPerSide = MaxOrdersPerSide;
+	type MaxTriggersPerMarket = MaxTriggersPerMarket;
+	type MaxMatchingSteps = MaxMatchingSteps;
+	type OrderDeposit = OrderDeposit;
+	type ListingOrigin = EnsureRootOrHalfCouncil;
+	type FeeOrigin = EnsureRootOrHalfCouncil;
+	type AdminOrigin = EnsureRootOrTwoThirdsCouncil;
+	type ListingBond = ListingBond;
+	type ListingChallengePeriod = ListingChallengePeriod;
+	type CandlePeriod = CandlePeriod;
+	type VolumeWindow = VolumeWindow;
+	type OracleGranularity = OracleGranularity;
+	type OracleObservations = OracleObservations;
+}
+
+parameter_types! {
+	pub const TokenDeposit: Balance = 10 * DOLLARS;
+	pub const MetadataDepositPerByte: Balance = 10 * CENTS;
+	pub const MaxClaimsCleared: u32 = 500;
+	pub const MinVestedShare: Permill = Permill::from_parts(10);
+	pub const UniqueSymbols: bool = true;
+}
+
+/// The exchange's escrow account, whose token balances belong to the traders with open
+/// orders rather than to the exchange.
+pub struct ExchangeEscrow;
+
+impl Get<Vec<AccountId>> for ExchangeEscrow {
+	fn get() -> Vec<AccountId> {
+		vec![Exchange::account_operation()]
+	}
+}
+
+/// The exchange's operation and vault accounts, which hold traders' escrow and the
+/// exchange's fees and must stay free to settle and cancel orders.
+pub struct ExchangeAccounts;
+
+impl Get<Vec<AccountId>> for ExchangeAccounts {
+	fn get() -> Vec<AccountId> {
+		vec![Exchange::account_operation(), Exchange::account_vault()]
+	}
 }
 
 impl pallet_token::Trait for Runtime {
 	type Event = Event;
 	type Currency = Balances;
+	type TransferHook = ();
+	type KycProvider = IdentityKyc;
+	type TokenDeposit = TokenDeposit;
+	type MetadataDepositPerByte = MetadataDepositPerByte;
+	type RegistryOrigin = EnsureRootOrHalfCouncil;
+	type UniqueSymbols = UniqueSymbols;
+	type SnapshotExcluded = ExchangeEscrow;
+	type EscrowAccounts = ExchangeAccounts;
+	type MaxClaimsCleared = MaxClaimsCleared;
+	type MinVestedShare = MinVestedShare;
 }
 
 
@@ -339,16 +658,26 @@ construct_runtime!(
 		Aura: pallet_aura::{Module, Config<T>, Inherent},
 		Grandpa: pallet_grandpa::{Module, Call, Storage, Config, Event},
 		Balances: pallet_balances::{Module, Call, Storage, Config<T>, Event<T>},
+		// Staking must build its genesis before Session, which asks it for the first validators.
+		Authorship: pallet_authorship::{Module, Call, Storage, Inherent},
+		Staking: pallet_staking::{Module, Call, Config<T>, Storage, Event<T>, ValidateUnsigned},
+		Session: pallet_session::{Module, Call, Storage, Event, Config<T>},
+		Historical: pallet_session_historical::{Module},
+		ImOnline: pallet_im_online::{Module, Call, Storage, Event<T>, ValidateUnsigned, Config<T>},
+		Offences: pallet_offences::{Module, Call, Storage, Event},
 		TransactionPayment: pallet_transaction_payment::{Module, Storage},
 		Sudo: pallet_sudo::{Module, Call, Config<T>, Storage, Event<T>},
+		Council: pallet_collective::<Instance1>::{Module, Call, Storage, Origin<T>, Event<T>, Config<T>},
+		NodeAuthorization: pallet_node_authorization::{Module, Call, Storage, Event<T>, Config<T>},
+		Identity: pallet_identity::{Module, Call, Storage, Event<T>},
 		
 		// Custom pallet
 		Contracts: pallet_contracts::{Module, Call, Config, Storage, Event<T>},
 		Scheduler: pallet_scheduler::{Module, Call, Storage, Event<T>},
-		Exchange: pallet_exchange::{Module, Call, Storage, Event<T>},
+		Exchange: pallet_exchange::{Module, Call, Storage, Config<T>, Event<T>},
 		Recovery: pallet_recovery::{Module, Call, Storage, Event<T>},
 		Multisig: pallet_multisig::{Module, Call, Storage, Event<T>},
-		Token: pallet_token::{Module, Call, Storage, Event<T>},
+		Token: pallet_token::{Module, Call, Storage, Config, Event<T>},
 
 	}
 );
@@ -510,6 +839,38 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl pallet_exchange_rpc_runtime_api::ExchangeApi<Block, Balance, BlockNumber> for Runtime {
+		fn last_price(market: pallet_exchange::Market) -> Option<Balance> {
+			Exchange::last_price(market)
+		}
+
+		fn volume_24h(market: pallet_exchange::Market) -> Balance {
+			Exchange::volume_24h(market)
+		}
+
+		fn candles(
+			market: pallet_exchange::Market,
+			from: BlockNumber,
+			to: BlockNumber,
+		) -> Vec<pallet_exchange::Candle<Balance, BlockNumber>> {
+			Exchange::candles(market, from, to)
+		}
+
+		fn twap(market: pallet_exchange::Market, window: BlockNumber) -> Option<Balance> {
+			Exchange::twap(market, window)
+		}
+	}
+
+	impl pallet_token_rpc_runtime_api::TokenApi<Block, AccountId, Balance> for Runtime {
+		fn pending_claim(distribution: pallet_token::DistributionIndex, who: AccountId) -> Balance {
+			Token::pending_claim(distribution, &who)
+		}
+
+		fn pending_claims(who: AccountId) -> Vec<(pallet_token::DistributionIndex, Balance)> {
+			Token::pending_claims(&who)
+		}
+	}
+
 	#[cfg(feature = "runtime-benchmarks")]
 	impl frame_benchmarking::Benchmark<Block> for Runtime {
 		fn dispatch_benchmark(